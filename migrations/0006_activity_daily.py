@@ -0,0 +1,43 @@
+# Generated by Django 3.1.2 on 2021-02-22 10:12
+
+from django.db import migrations, models
+
+
+class Migration(migrations.Migration):
+
+    dependencies = [
+        ('realm', '0005_auto_20210215_1406'),
+    ]
+
+    operations = [
+        migrations.CreateModel(
+            name='ActivityDaily',
+            fields=[
+                ('id', models.AutoField(auto_created=True, primary_key=True, serialize=False, verbose_name='ID')),
+                ('day', models.DateField()),
+                ('method', models.TextField()),
+                ('route', models.TextField()),
+                ('outcome', models.TextField()),
+                ('code', models.TextField()),
+                ('count', models.IntegerField()),
+                ('p50', models.IntegerField()),
+                ('p95', models.IntegerField()),
+            ],
+            options={
+                'db_table': 'realm_activity_daily',
+                'unique_together': {('day', 'method', 'route', 'outcome', 'code')},
+            },
+        ),
+        migrations.AddIndex(
+            model_name='activity',
+            index=models.Index(fields=['when'], name='realm_activ_when_483fdf_idx'),
+        ),
+        migrations.AddIndex(
+            model_name='activity',
+            index=models.Index(fields=['tid'], name='realm_activ_tid_e0c0f1_idx'),
+        ),
+        migrations.AddIndex(
+            model_name='activity',
+            index=models.Index(fields=['vid'], name='realm_activ_vid_332b1c_idx'),
+        ),
+    ]
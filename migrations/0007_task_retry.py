@@ -0,0 +1,29 @@
+# Generated by Django 3.1.2 on 2021-02-24 09:41
+
+from django.db import migrations, models
+import django.utils.timezone
+
+
+class Migration(migrations.Migration):
+
+    dependencies = [
+        ('realm', '0006_activity_daily'),
+    ]
+
+    operations = [
+        migrations.AddField(
+            model_name='task',
+            name='last_error',
+            field=models.TextField(null=True),
+        ),
+        migrations.AddField(
+            model_name='task',
+            name='run_after',
+            field=models.DateTimeField(default=django.utils.timezone.now),
+        ),
+        # failed used to be final, failed tasks are retried now
+        migrations.RunSQL(
+            "UPDATE realm_task SET status = 'dead' WHERE status = 'failed'",
+            migrations.RunSQL.noop,
+        ),
+    ]
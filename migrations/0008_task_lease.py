@@ -0,0 +1,22 @@
+# Generated by Django 3.1.2 on 2021-02-25 11:03
+
+from django.db import migrations, models
+
+
+class Migration(migrations.Migration):
+
+    dependencies = [
+        ('realm', '0007_task_retry'),
+    ]
+
+    operations = [
+        migrations.AddField(
+            model_name='task',
+            name='lease_until',
+            field=models.DateTimeField(null=True),
+        ),
+        migrations.AddIndex(
+            model_name='task',
+            index=models.Index(condition=models.Q(status__in=['created', 'failed', 'in_progress']), fields=['-priority', 'created_on'], name='realm_task_pending_idx'),
+        ),
+    ]
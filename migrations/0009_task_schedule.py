@@ -0,0 +1,27 @@
+# Generated by Django 3.1.2 on 2021-02-26 08:27
+
+from django.db import migrations, models
+
+
+class Migration(migrations.Migration):
+
+    dependencies = [
+        ('realm', '0008_task_lease'),
+    ]
+
+    operations = [
+        migrations.AddField(
+            model_name='task',
+            name='schedule',
+            field=models.TextField(null=True),
+        ),
+        migrations.AddField(
+            model_name='task',
+            name='scheduled_for',
+            field=models.DateTimeField(null=True),
+        ),
+        migrations.AlterUniqueTogether(
+            name='task',
+            unique_together={('schedule', 'scheduled_for')},
+        ),
+    ]
@@ -0,0 +1,18 @@
+# Generated by Django 3.1.2 on 2021-03-01 06:12
+
+from django.db import migrations, models
+
+
+class Migration(migrations.Migration):
+
+    dependencies = [
+        ('realm', '0009_task_schedule'),
+    ]
+
+    operations = [
+        migrations.AddField(
+            model_name='task',
+            name='key',
+            field=models.TextField(null=True, unique=True),
+        ),
+    ]
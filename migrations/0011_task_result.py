@@ -0,0 +1,38 @@
+# Generated by Django 3.1.2 on 2021-03-03 10:41
+
+from django.db import migrations, models
+
+
+class Migration(migrations.Migration):
+
+    dependencies = [
+        ('realm', '0010_task_key'),
+    ]
+
+    operations = [
+        migrations.AddField(
+            model_name='task',
+            name='result',
+            field=models.JSONField(null=True),
+        ),
+        migrations.AddField(
+            model_name='task',
+            name='started_on',
+            field=models.DateTimeField(null=True),
+        ),
+        migrations.AddField(
+            model_name='task',
+            name='finished_on',
+            field=models.DateTimeField(null=True),
+        ),
+        migrations.AddField(
+            model_name='task',
+            name='duration',
+            field=models.IntegerField(null=True),
+        ),
+        migrations.AddField(
+            model_name='task',
+            name='created_by',
+            field=models.TextField(null=True),
+        ),
+    ]
@@ -1,4 +1,5 @@
 from django.db import models
+from django.utils import timezone
 
 
 class Activity(models.Model):
@@ -38,6 +39,29 @@ class Activity(models.Model):
 
     site_version = models.TextField()
 
+    class Meta:
+        indexes = [
+            models.Index(fields=["when"]),
+            models.Index(fields=["tid"]),
+            models.Index(fields=["vid"]),
+        ]
+
+
+class ActivityDaily(models.Model):
+    day = models.DateField()
+    method = models.TextField()
+    route = models.TextField()
+    outcome = models.TextField()
+    code = models.TextField()
+
+    count = models.IntegerField()
+    p50 = models.IntegerField()
+    p95 = models.IntegerField()
+
+    class Meta:
+        db_table = "realm_activity_daily"
+        unique_together = [("day", "method", "route", "outcome", "code")]
+
 
 class Task(models.Model):
     path = models.TextField()
@@ -47,8 +71,36 @@ class Task(models.Model):
 
     priority = models.IntegerField()
 
+    # created, in_progress, failed (will be retried), processed, dead
     status = models.TextField()
     number_tries = models.IntegerField()
+    run_after = models.DateTimeField(default=timezone.now)
+    last_error = models.TextField(null=True)
+    # in_progress tasks are claimed by a worker till this time
+    lease_until = models.DateTimeField(null=True)
+    # name and occurrence of the schedule that created this task
+    schedule = models.TextField(null=True)
+    scheduled_for = models.DateTimeField(null=True)
+    # idempotency key, unique while set, cleared after REALM_TASK_DEDUP_SECS
+    key = models.TextField(null=True, unique=True)
+    # json returned by the handler
+    result = models.JSONField(null=True)
+    # of the last attempt, duration in milliseconds
+    started_on = models.DateTimeField(null=True)
+    finished_on = models.DateTimeField(null=True)
+    duration = models.IntegerField(null=True)
+    # uid of the user whose request created the task, can see its status
+    created_by = models.TextField(null=True)
 
     created_on = models.DateTimeField(auto_now_add=True)
     updated_on = models.DateTimeField(auto_now=True)
+
+    class Meta:
+        indexes = [
+            models.Index(
+                fields=["-priority", "created_on"],
+                name="realm_task_pending_idx",
+                condition=models.Q(status__in=["created", "failed", "in_progress"]),
+            ),
+        ]
+        unique_together = [("schedule", "scheduled_for")]
@@ -100,3 +100,70 @@ pub fn realm_page(meta: TokenStream, input: TokenStream) -> TokenStream {
 
     q.into()
 }
+
+// embed_assets!() is meant to be used in release builds, so the binary can be
+// deployed without the static folder and ftd.js, the page shell is an askama
+// template so it is always part of the binary:
+//
+//     static ASSETS: realm::embed::Assets = realm_macros::embed_assets!();
+//     realm::embed::register(&ASSETS);
+//
+// files are included using include_bytes!() so a change in content triggers a
+// rebuild, but new files in static/ are only picked up on the next rebuild.
+#[proc_macro]
+pub fn embed_assets(_input: TokenStream) -> TokenStream {
+    let root = std::path::PathBuf::from(
+        std::env::var("CARGO_MANIFEST_DIR").unwrap_or_else(|_| ".".to_string()),
+    );
+
+    let mut files = vec![];
+    utils::walk(&root.join("static"), &mut files);
+
+    let names: Vec<String> = files
+        .iter()
+        .map(|path| {
+            format!(
+                "/{}",
+                path.strip_prefix(&root)
+                    .unwrap()
+                    .to_string_lossy()
+                    .replace('\\', "/")
+            )
+        })
+        .collect();
+
+    let assets: Vec<proc_macro2::TokenStream> = files
+        .iter()
+        .zip(names.iter())
+        .map(|(path, name)| {
+            let full = path.to_string_lossy().to_string();
+            let hash = utils::sha256(&std::fs::read(path).unwrap());
+            quote! {
+                realm::embed::Asset {
+                    path: #name,
+                    content: include_bytes!(#full),
+                    hash: #hash,
+                }
+            }
+        })
+        .collect();
+
+    let ftd_js = optional_include_str(&root.join("ftd/ftd.js"));
+
+    (quote! {
+        realm::embed::Assets {
+            ftd_js: #ftd_js,
+            files: &[#(#assets),*],
+        }
+    })
+    .into()
+}
+
+fn optional_include_str(path: &std::path::Path) -> proc_macro2::TokenStream {
+    if path.is_file() {
+        let path = path.to_string_lossy().to_string();
+        quote! { Some(include_str!(#path)) }
+    } else {
+        quote! { None }
+    }
+}
@@ -14,6 +14,28 @@ pub fn convert_id_to_html_path(id_: &str) -> String {
     html_path
 }
 
+pub fn walk(dir: &std::path::Path, files: &mut Vec<std::path::PathBuf>) {
+    let mut entries: Vec<_> = match std::fs::read_dir(dir) {
+        Ok(entries) => entries.filter_map(|e| e.ok()).map(|e| e.path()).collect(),
+        Err(_) => return,
+    };
+    entries.sort();
+
+    for path in entries.into_iter() {
+        if path.is_dir() {
+            walk(&path, files);
+        } else {
+            files.push(path);
+        }
+    }
+}
+
+pub fn sha256(content: &[u8]) -> String {
+    use sha2::Digest;
+
+    format!("{:x}", sha2::Sha256::digest(content))
+}
+
 #[cfg(test)]
 mod tests {
     #[test]
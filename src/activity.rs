@@ -1,47 +1,102 @@
-#[cfg(feature = "postgres")]
-table! {
-    realm_activity (id) {
-        id -> Integer,
-        okind -> Text,
-        oid -> Text,
-        ekind -> Text,
-        data -> Text,
-        trace -> Text,
-        response -> Text,
-        when -> Timestamptz,
-        ip -> Text,
-        app -> Text,
-        site_version -> Text,
-        session_id -> Nullable<Text>,
-        who_id -> Nullable<Text>,
-        url -> Text,
-    }
-}
-
-#[cfg(feature = "sqlite")]
-table! {
-    realm_activity (id) {
-        id -> Integer,
-        okind -> Text,
-        oid -> Text,
-        ekind -> Text,
-        data -> Text,
-        trace -> Text,
-        response -> Text,
-        when -> Timestamp,
-        ip -> Text,
-        app -> Text,
-        site_version -> Text,
-        session_id -> Nullable<Text>,
-        who_id -> Nullable<Text>,
-        url -> Text,
-    }
-}
+// Every request records one activity, what kind of object (okind) with which
+// id (oid) was acted upon and how (ekind), along with free form data. It is
+// filled by handlers using In::activity() / In::activity_data(), and by pages
+// using Page::with_activity(), and stored in realm_activity by end_context().
 
-#[derive(Debug)]
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq, Default)]
 pub struct Activity {
     pub okind: String,
     pub oid: String,
     pub ekind: String,
     pub data: serde_json::Value,
 }
+
+pub(crate) const P1_NAME: &str = "realm.rr.step.activity";
+
+impl Activity {
+    pub fn new(okind: &str, oid: &str, ekind: &str) -> Self {
+        Activity {
+            okind: okind.to_string(),
+            oid: oid.to_string(),
+            ekind: ekind.to_string(),
+            data: serde_json::Value::Null,
+        }
+    }
+
+    // data is always an object once a key is added, existing key is replaced
+    pub fn add_data(&mut self, key: &str, value: serde_json::Value) {
+        if !self.data.is_object() {
+            self.data = serde_json::Value::Object(serde_json::Map::new());
+        }
+        if let Some(o) = self.data.as_object_mut() {
+            o.insert(key.to_string(), value);
+        }
+    }
+
+    // non empty fields of other win, data is merged key by key
+    pub fn merge(&mut self, other: &Activity) {
+        if !other.okind.is_empty() {
+            self.okind = other.okind.clone();
+        }
+        if !other.oid.is_empty() {
+            self.oid = other.oid.clone();
+        }
+        if !other.ekind.is_empty() {
+            self.ekind = other.ekind.clone();
+        }
+        match other.data {
+            serde_json::Value::Null => {}
+            serde_json::Value::Object(ref o) => {
+                for (k, v) in o.iter() {
+                    self.add_data(k, v.clone());
+                }
+            }
+            ref v => self.add_data("data", v.clone()),
+        }
+    }
+
+    pub fn to_p1(&self) -> crate::base::Result<ftd::p1::SubSection> {
+        let mut p1 = ftd::p1::SubSection {
+            name: P1_NAME.to_string(),
+            body: Some(serde_json::to_string_pretty(&self.data)?),
+            ..Default::default()
+        };
+        p1.header.add("okind", self.okind.as_str());
+        p1.header.add("oid", self.oid.as_str());
+        p1.header.add("ekind", self.ekind.as_str());
+
+        Ok(p1)
+    }
+
+    pub fn from_p1(p1: &ftd::p1::SubSection) -> crate::base::Result<Self> {
+        Ok(Activity {
+            okind: p1.header.string_with_default("okind", "")?,
+            oid: p1.header.string_with_default("oid", "")?,
+            ekind: p1.header.string_with_default("ekind", "")?,
+            data: serde_json::from_str(p1.body()?.as_str())?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn merge() {
+        let mut a = super::Activity::new("article", "", "view");
+        a.add_data("lang", json!("en"));
+
+        let mut page = super::Activity::new("", "hello", "");
+        page.data = json!({"lang": "hi", "success": false});
+        a.merge(&page);
+
+        assert_eq!(
+            a,
+            super::Activity {
+                okind: "article".to_string(),
+                oid: "hello".to_string(),
+                ekind: "view".to_string(),
+                data: json!({"lang": "hi", "success": false}),
+            }
+        );
+    }
+}
@@ -0,0 +1,396 @@
+// What end_context() stores about a request is decided by a Policy, instead of
+// hard-coded path checks. The policy is read from the json file pointed to by
+// REALM_ACTIVITY_POLICY, or set by the app at startup with
+// realm::activity_policy::set(), eg:
+//
+//     {
+//         "skip": ["/static/*", "*.php", "/health/"],
+//         "sample": [{"pattern": "/api/*", "rate": 0.1}],
+//         "redact": ["/data/password", "/response/users/*/email"],
+//         "hash": ["/data/email"],
+//         "redact_query": ["token"],
+//         "ip": "truncate",
+//         "drop_trace_on_success": true
+//     }
+//
+// Patterns match the lower cased path, `*` matches any run of characters. The
+// first matching sample rate wins, unmatched paths are always stored. Redact
+// and hash entries are json pointers, starting with /data or /response, `*`
+// matches every element of an array or object. Sampling is not applied in test
+// mode so recordings stay complete.
+//
+// A missing or invalid REALM_ACTIVITY_POLICY file fails realm::env::init().
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq)]
+pub struct Sample {
+    pub pattern: String,
+    // 0.0 to 1.0, fraction of matching requests to store
+    pub rate: f64,
+}
+
+#[allow(clippy::upper_case_acronyms)]
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum IP {
+    Full,
+    // last octet of ipv4, last 80 bits of ipv6 are zeroed
+    Truncate,
+    Drop,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct Policy {
+    pub skip: Vec<String>,
+    pub skip_crawlers: bool,
+    pub sample: Vec<Sample>,
+    pub redact: Vec<String>,
+    pub hash: Vec<String>,
+    // query parameters whose values are redacted in the stored url
+    pub redact_query: Vec<String>,
+    pub ip: IP,
+    pub drop_trace_on_success: bool,
+}
+
+impl Default for Policy {
+    fn default() -> Self {
+        Policy {
+            skip: vec![
+                "*.php".to_string(),
+                "*.png".to_string(),
+                "/static/*".to_string(),
+                "/favicon.ico".to_string(),
+                "/test/*".to_string(),
+                "/iframe/".to_string(),
+            ],
+            skip_crawlers: true,
+            sample: vec![],
+            redact: vec![],
+            hash: vec![],
+            redact_query: vec![],
+            ip: IP::Full,
+            drop_trace_on_success: false,
+        }
+    }
+}
+
+const REDACTED: &str = "[redacted]";
+
+lazy_static! {
+    static ref POLICY: antidote::RwLock<Option<std::sync::Arc<Policy>>> =
+        antidote::RwLock::new(None);
+}
+
+fn from_env() -> Result<Policy, failure::Error> {
+    match std::env::var("REALM_ACTIVITY_POLICY") {
+        Ok(path) => {
+            let content = std::fs::read_to_string(&path)
+                .map_err(|e| format_err!("REALM_ACTIVITY_POLICY: can't read {}: {}", path, e))?;
+            serde_json::from_str(content.as_str())
+                .map_err(|e| format_err!("REALM_ACTIVITY_POLICY: invalid {}: {}", path, e))
+        }
+        Err(_) => Ok(Policy::default()),
+    }
+}
+
+// called by realm::env::init(), a policy set() before it is kept
+pub fn init() -> Result<(), failure::Error> {
+    if POLICY.read().is_none() {
+        set(from_env()?);
+    }
+    Ok(())
+}
+
+pub fn set(policy: Policy) {
+    *POLICY.write() = Some(std::sync::Arc::new(policy));
+}
+
+pub fn policy() -> std::sync::Arc<Policy> {
+    if let Some(ref p) = *POLICY.read() {
+        return p.clone();
+    }
+
+    let mut p = POLICY.write();
+    p.get_or_insert_with(|| {
+        std::sync::Arc::new(from_env().unwrap_or_else(|e| {
+            eprintln!("realm::activity_policy: {}, using the default policy", e);
+            Policy::default()
+        }))
+    })
+    .clone()
+}
+
+// `*` matches any run of characters, including none
+pub fn matches(pattern: &str, path: &str) -> bool {
+    let parts: Vec<&str> = pattern.split('*').collect();
+    if parts.len() == 1 {
+        return pattern == path;
+    }
+
+    let first = parts[0];
+    let last = parts[parts.len() - 1];
+    if path.len() < first.len() + last.len() || !path.starts_with(first) || !path.ends_with(last) {
+        return false;
+    }
+
+    let mut rest = &path[first.len()..path.len() - last.len()];
+    for part in parts[1..parts.len() - 1].iter() {
+        match rest.find(part) {
+            Some(i) => rest = &rest[i + part.len()..],
+            None => return false,
+        }
+    }
+    true
+}
+
+pub fn truncate_ip(ip: &str) -> String {
+    // x-forwarded-for can be a list of addresses
+    ip.split(',')
+        .map(|ip| match ip.trim().parse::<std::net::IpAddr>() {
+            Ok(std::net::IpAddr::V4(v4)) => {
+                let o = v4.octets();
+                std::net::Ipv4Addr::new(o[0], o[1], o[2], 0).to_string()
+            }
+            Ok(std::net::IpAddr::V6(v6)) => {
+                let s = v6.segments();
+                std::net::Ipv6Addr::new(s[0], s[1], s[2], 0, 0, 0, 0, 0).to_string()
+            }
+            Err(_) => "".to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn hash(value: &serde_json::Value) -> serde_json::Value {
+    use sha2::Digest;
+
+    let s = match value {
+        serde_json::Value::String(s) => s.clone(),
+        v => v.to_string(),
+    };
+    json!(format!("sha256:{:x}", sha2::Sha256::digest(s.as_bytes())))
+}
+
+// calls f on every value the pointer segments lead to
+fn walk(value: &mut serde_json::Value, segments: &[String], f: &dyn Fn(&mut serde_json::Value)) {
+    let (first, rest) = match segments.split_first() {
+        Some(v) => v,
+        None => return f(value),
+    };
+
+    match value {
+        serde_json::Value::Object(o) if first == "*" => {
+            o.values_mut().for_each(|v| walk(v, rest, f))
+        }
+        serde_json::Value::Object(o) => {
+            if let Some(v) = o.get_mut(first) {
+                walk(v, rest, f)
+            }
+        }
+        serde_json::Value::Array(a) if first == "*" => a.iter_mut().for_each(|v| walk(v, rest, f)),
+        serde_json::Value::Array(a) => {
+            if let Some(v) = first.parse::<usize>().ok().and_then(|i| a.get_mut(i)) {
+                walk(v, rest, f)
+            }
+        }
+        _ => {}
+    }
+}
+
+fn segments(pointer: &str) -> Vec<String> {
+    pointer
+        .split('/')
+        .skip(1)
+        .map(|s| s.replace("~1", "/").replace("~0", "~"))
+        .collect()
+}
+
+fn apply_pointers(
+    record: &mut crate::activity_sink::Record,
+    pointers: &[String],
+    f: &dyn Fn(&mut serde_json::Value),
+) {
+    for pointer in pointers.iter() {
+        let segments = segments(pointer);
+        match segments.split_first() {
+            Some((root, rest)) if root == "data" => walk(&mut record.data, rest, f),
+            Some((root, rest)) if root == "response" => walk(&mut record.response, rest, f),
+            _ => {}
+        }
+    }
+}
+
+fn redact_query(url: &str, names: &[String]) -> String {
+    let mut url = match url::Url::parse(url) {
+        Ok(u) => u,
+        Err(_) => return url.to_string(),
+    };
+    if !url
+        .query_pairs()
+        .any(|(k, _)| names.iter().any(|n| n == &k))
+    {
+        return url.to_string();
+    }
+
+    let pairs: Vec<(String, String)> = url
+        .query_pairs()
+        .map(|(k, v)| {
+            if names.iter().any(|n| n == &k) {
+                (k.to_string(), REDACTED.to_string())
+            } else {
+                (k.to_string(), v.to_string())
+            }
+        })
+        .collect();
+    url.query_pairs_mut().clear().extend_pairs(pairs.iter());
+    url.to_string()
+}
+
+impl Policy {
+    pub fn is_skipped(&self, path: &str, is_crawler: bool) -> bool {
+        if is_crawler && self.skip_crawlers {
+            return true;
+        }
+
+        let path = path.to_lowercase();
+        self.skip.iter().any(|p| matches(p.as_str(), path.as_str()))
+    }
+
+    pub fn sample_rate(&self, path: &str) -> f64 {
+        let path = path.to_lowercase();
+        self.sample
+            .iter()
+            .find(|s| matches(s.pattern.as_str(), path.as_str()))
+            .map(|s| s.rate)
+            .unwrap_or(1.0)
+    }
+
+    pub fn should_store(&self, path: &str, is_crawler: bool) -> bool {
+        if self.is_skipped(path, is_crawler) {
+            return false;
+        }
+        if crate::base::is_test() {
+            return true;
+        }
+
+        let rate = self.sample_rate(path);
+        rate >= 1.0 || rand::random::<f64>() < rate
+    }
+
+    // strips what the policy does not allow to be stored
+    pub fn apply(&self, record: &mut crate::activity_sink::Record) {
+        apply_pointers(record, &self.redact, &|v| *v = json!(REDACTED));
+        apply_pointers(record, &self.hash, &|v| *v = hash(v));
+
+        if !self.redact_query.is_empty() {
+            record.url = redact_query(record.url.as_str(), &self.redact_query);
+        }
+
+        match self.ip {
+            IP::Full => {}
+            IP::Truncate => record.ip = truncate_ip(record.ip.as_str()),
+            IP::Drop => record.ip = "".to_string(),
+        }
+
+        if self.drop_trace_on_success && record.outcome == "success" {
+            record.trace = serde_json::Value::Null;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn matches() {
+        assert!(super::matches("/static/*", "/static/a.js"));
+        assert!(super::matches("*.php", "/wp-login.php"));
+        assert!(super::matches("/api/*/edit/", "/api/12/edit/"));
+        assert!(super::matches("/iframe/", "/iframe/"));
+        assert!(!super::matches("/iframe/", "/iframe/x/"));
+        assert!(!super::matches("/api/*/edit/", "/api/edit/"));
+        assert!(!super::matches("/a*a", "/a"));
+    }
+
+    #[test]
+    fn truncate_ip() {
+        assert_eq!(super::truncate_ip("1.2.3.4"), "1.2.3.0");
+        assert_eq!(
+            super::truncate_ip("2001:db8:85a3::8a2e:370:7334, 10.0.0.7"),
+            "2001:db8:85a3::, 10.0.0.0"
+        );
+        assert_eq!(super::truncate_ip(""), "");
+    }
+
+    #[test]
+    fn apply() {
+        let policy = super::Policy {
+            redact: vec![
+                "/data/password".to_string(),
+                "/response/users/*/email".to_string(),
+            ],
+            hash: vec!["/data/email".to_string()],
+            redact_query: vec!["token".to_string()],
+            ip: super::IP::Truncate,
+            drop_trace_on_success: true,
+            ..Default::default()
+        };
+
+        let mut record: crate::activity_sink::Record = serde_json::from_value(json!({
+            "url": "https://example.com/login/?token=abc&next=/",
+            "method": "POST",
+            "ua": "",
+            "ip": "1.2.3.4",
+            "okind": "user",
+            "oid": "",
+            "ekind": "login",
+            "data": {"password": "secret", "email": "a@example.com"},
+            "uid": null,
+            "sid": null,
+            "vid": "v",
+            "vid_created": false,
+            "tid": "t",
+            "tid_created": false,
+            "when": "2021-02-13T04:05:06Z",
+            "duration": 10,
+            "response": {"users": [{"email": "a@example.com", "name": "a"}]},
+            "outcome": "success",
+            "code": "success",
+            "trace": {"span_stack": []},
+            "hash": "",
+            "rust_trace": null,
+            "utm_source": null,
+            "utm_medium": null,
+            "utm_campaign": null,
+            "utm_term": null,
+            "utm_content": null,
+            "site_version": ""
+        }))
+        .unwrap();
+        policy.apply(&mut record);
+
+        assert_eq!(
+            record.url,
+            "https://example.com/login/?token=%5Bredacted%5D&next=%2F"
+        );
+        assert_eq!(record.ip, "1.2.3.0");
+        assert_eq!(record.data["password"], json!("[redacted]"));
+        assert!(record.data["email"]
+            .as_str()
+            .unwrap()
+            .starts_with("sha256:"));
+        assert_eq!(
+            record.response,
+            json!({"users": [{"email": "[redacted]", "name": "a"}]})
+        );
+        assert_eq!(record.trace, serde_json::Value::Null);
+    }
+
+    #[test]
+    fn default_skips() {
+        let policy = super::Policy::default();
+        assert!(policy.is_skipped("/static/x.js", false));
+        assert!(policy.is_skipped("/Index.PHP", false));
+        assert!(policy.is_skipped("/", true));
+        assert!(!policy.is_skipped("/", false));
+    }
+}
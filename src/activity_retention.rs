@@ -0,0 +1,272 @@
+// Maintenance of realm_activity, which otherwise grows without bound:
+//
+// - rollup: complete days are summarised into realm_activity_daily, request
+//   count and p50/p95 duration by method, route (url path), outcome and code.
+//   Days from the last rolled up one till yesterday are (re)computed, so the
+//   job can be run as often as one likes.
+// - prune: raw rows older than REALM_ACTIVITY_RETENTION_DAYS are deleted, whole
+//   days at a time, after they are rolled up. If REALM_ACTIVITY_ARCHIVE_DIR is
+//   set they are first appended to a gzipped json lines file in that folder.
+//
+// Nothing is pruned if REALM_ACTIVITY_RETENTION_DAYS is not set, a value that
+// is not a number fails realm::env::init(). The jobs are run by a thread of
+// the worker once every REALM_ACTIVITY_MAINTENANCE_HOURS (default 24), or from
+// command line with `--activity-maintenance`. A postgres advisory lock makes
+// sure only one process runs them at a time.
+
+use crate::base::pg::RealmConnection;
+use chrono::{Duration, NaiveDate, TimeZone, Utc};
+use diesel::prelude::*;
+
+lazy_static! {
+    static ref RETENTION_DAYS: Result<Option<i64>, failure::Error> = retention_days();
+    static ref ARCHIVE_DIR: Option<std::path::PathBuf> =
+        std::env::var("REALM_ACTIVITY_ARCHIVE_DIR")
+            .ok()
+            .map(std::path::PathBuf::from);
+    static ref INTERVAL: std::time::Duration = std::time::Duration::from_secs(
+        std::env::var("REALM_ACTIVITY_MAINTENANCE_HOURS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(24)
+            * 3600
+    );
+    static ref LAST_RUN: antidote::Mutex<Option<std::time::Instant>> = antidote::Mutex::new(None);
+}
+
+fn retention_days() -> Result<Option<i64>, failure::Error> {
+    match std::env::var("REALM_ACTIVITY_RETENTION_DAYS") {
+        Ok(v) => v
+            .trim()
+            .parse()
+            .map(|v: i64| Some(v.max(1)))
+            .map_err(|_| format_err!("REALM_ACTIVITY_RETENTION_DAYS must be a number: {}", v)),
+        Err(_) => Ok(None),
+    }
+}
+
+// called by realm::env::init()
+pub fn init() -> Result<(), failure::Error> {
+    match *RETENTION_DAYS {
+        Ok(_) => Ok(()),
+        Err(ref e) => Err(format_err!("{}", e)),
+    }
+}
+
+// arbitrary, has to be unique among advisory locks used by the app
+const LOCK_KEY: i64 = 7_310_233_001;
+const BATCH_SIZE: i64 = 1000;
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Report {
+    // realm_activity_daily rows inserted or updated
+    pub rolled_up: usize,
+    pub archived: usize,
+    pub deleted: usize,
+}
+
+#[derive(QueryableByName)]
+struct Locked {
+    #[sql_type = "diesel::sql_types::Bool"]
+    locked: bool,
+}
+
+fn lock(conn: &RealmConnection) -> crate::base::Result<bool> {
+    let l: Locked = diesel::sql_query("SELECT pg_try_advisory_lock($1) AS locked")
+        .bind::<diesel::sql_types::BigInt, _>(LOCK_KEY)
+        .get_result(conn)?;
+    Ok(l.locked)
+}
+
+fn unlock(conn: &RealmConnection) -> crate::base::Result<()> {
+    diesel::sql_query("SELECT pg_advisory_unlock($1)")
+        .bind::<diesel::sql_types::BigInt, _>(LOCK_KEY)
+        .execute(conn)?;
+    Ok(())
+}
+
+fn start_of(day: NaiveDate) -> chrono::DateTime<Utc> {
+    Utc.from_utc_date(&day).and_hms(0, 0, 0)
+}
+
+pub fn rollup(conn: &RealmConnection) -> crate::base::Result<usize> {
+    use crate::schema::realm_activity_daily;
+
+    let today = Utc::today().naive_utc();
+    let from = realm_activity_daily::table
+        .select(diesel::dsl::max(realm_activity_daily::day))
+        .first::<Option<NaiveDate>>(conn)?
+        .map(start_of)
+        .unwrap_or_else(|| Utc.timestamp(0, 0));
+
+    diesel::sql_query(
+        "INSERT INTO realm_activity_daily \
+         (day, method, route, outcome, code, count, p50, p95) \
+         SELECT \
+         (\"when\" AT TIME ZONE 'UTC')::date, \
+         method, \
+         split_part(regexp_replace(url, '^[a-zA-Z]+://[^/]*', ''), '?', 1), \
+         outcome, \
+         code, \
+         count(*), \
+         coalesce(percentile_cont(0.5) WITHIN GROUP (ORDER BY duration) \
+         FILTER (WHERE duration >= 0), -1)::integer, \
+         coalesce(percentile_cont(0.95) WITHIN GROUP (ORDER BY duration) \
+         FILTER (WHERE duration >= 0), -1)::integer \
+         FROM realm_activity \
+         WHERE \"when\" >= $1 AND \"when\" < $2 \
+         GROUP BY 1, 2, 3, 4, 5 \
+         ON CONFLICT (day, method, route, outcome, code) DO UPDATE SET \
+         count = EXCLUDED.count, p50 = EXCLUDED.p50, p95 = EXCLUDED.p95",
+    )
+    .bind::<diesel::sql_types::Timestamptz, _>(from)
+    .bind::<diesel::sql_types::Timestamptz, _>(start_of(today))
+    .execute(conn)
+    .map_err(Into::into)
+}
+
+fn archive(
+    dir: &std::path::Path,
+    cutoff: NaiveDate,
+    records: &[crate::activity_sink::Record],
+) -> crate::base::Result<()> {
+    use std::io::Write;
+
+    std::fs::create_dir_all(dir)?;
+    let file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(dir.join(format!("realm_activity-until-{}.jsonl.gz", cutoff)))?;
+
+    // every call appends a gzip member, concatenated members are a valid gzip file
+    let mut gz = flate2::write::GzEncoder::new(file, flate2::Compression::default());
+    for r in records.iter() {
+        serde_json::to_writer(&mut gz, r)?;
+        gz.write_all(b"\n")?;
+    }
+    gz.finish()?;
+    Ok(())
+}
+
+// returns (archived, deleted)
+pub fn prune(conn: &RealmConnection, retention_days: i64) -> crate::base::Result<(usize, usize)> {
+    use crate::schema::realm_activity as a;
+
+    let cutoff_day = Utc::today().naive_utc() - Duration::days(retention_days.max(1));
+    let cutoff = start_of(cutoff_day);
+    let (mut archived, mut deleted) = (0, 0);
+
+    loop {
+        let rows: Vec<(i32, crate::activity_sink::Record)> = a::table
+            .select((
+                a::id,
+                (
+                    a::url,
+                    a::method,
+                    a::ua,
+                    a::ip,
+                    a::okind,
+                    a::oid,
+                    a::ekind,
+                    a::data,
+                    a::uid,
+                    a::sid,
+                    a::vid,
+                    a::vid_created,
+                    a::tid,
+                    a::tid_created,
+                    a::when,
+                    a::duration,
+                    a::response,
+                    a::outcome,
+                    a::code,
+                    a::trace,
+                    a::hash,
+                    a::rust_trace,
+                    a::utm_source,
+                    a::utm_medium,
+                    a::utm_campaign,
+                    a::utm_term,
+                    a::utm_content,
+                    a::site_version,
+                ),
+            ))
+            .filter(a::when.lt(cutoff))
+            .order(a::id)
+            .limit(BATCH_SIZE)
+            .load(conn)?;
+        if rows.is_empty() {
+            break;
+        }
+
+        let ids: Vec<i32> = rows.iter().map(|(id, _)| *id).collect();
+        if let Some(ref dir) = *ARCHIVE_DIR {
+            let records: Vec<_> = rows.into_iter().map(|(_, r)| r).collect();
+            archive(dir, cutoff_day, &records)?;
+            archived += records.len();
+        }
+        deleted += diesel::delete(a::table.filter(a::id.eq_any(ids))).execute(conn)?;
+    }
+
+    if deleted > 0 {
+        diesel::sql_query("ANALYZE realm_activity").execute(conn)?;
+    }
+    Ok((archived, deleted))
+}
+
+pub fn run(conn: &RealmConnection) -> crate::base::Result<Report> {
+    if !lock(conn)? {
+        println!("activity maintenance is running in another process");
+        return Ok(Report::default());
+    }
+
+    let result = rollup(conn).and_then(|rolled_up| {
+        let (archived, deleted) = match *RETENTION_DAYS {
+            Ok(Some(days)) => prune(conn, days)?,
+            Ok(None) => (0, 0),
+            Err(ref e) => return Err(format_err!("{}", e)),
+        };
+        Ok(Report {
+            rolled_up,
+            archived,
+            deleted,
+        })
+    });
+
+    unlock(conn)?;
+    result
+}
+
+fn is_due() -> bool {
+    let mut last = LAST_RUN.lock();
+    if let Some(t) = *last {
+        if t.elapsed() < *INTERVAL {
+            return false;
+        }
+    }
+    *last = Some(std::time::Instant::now());
+    true
+}
+
+// runs the jobs once every INTERVAL, till ctrl-c, on a thread of its own so a
+// long prune does not hold up tasks. A connection is taken only while running.
+pub fn start() {
+    let r = std::thread::Builder::new()
+        .name("realm-activity-maintenance".to_string())
+        .spawn(|| {
+            while !crate::env::ctrl_c().unwrap_or(true) {
+                if is_due() {
+                    if let Err(e) = crate::base::pg::try_connection().and_then(|c| run(&c)) {
+                        observer::observe_string(
+                            "activity_maintenance_err",
+                            format!("{}", e).as_str(),
+                        );
+                    }
+                }
+                std::thread::sleep(std::time::Duration::from_secs(60));
+            }
+        });
+    if let Err(e) = r {
+        eprintln!("failed to start activity maintenance: {}", e);
+    }
+}
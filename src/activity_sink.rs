@@ -0,0 +1,413 @@
+// end_context() does not write activity itself, it hands a Record to the sink
+// chosen at startup. Records are queued and written in batches by a background
+// thread, so storing activity never slows down or fails a request; if the
+// queue is full records are dropped.
+//
+// The sink is picked by REALM_ACTIVITY_SINK:
+//
+// - postgres: realm_activity table, default
+// - sqlite: realm_activity table in DATABASE_URL sqlite file
+// - jsonl:<path>: one json record per line, appended to path
+// - memory: kept in memory, for tests, see MemorySink::records()
+// - none: activity is not stored
+//
+// or by calling realm::activity_sink::set() before the first request, for a
+// custom sink. An unknown sink fails realm::env::init() at startup. Batching
+// is controlled by REALM_ACTIVITY_BATCH_SIZE (default 100) and
+// REALM_ACTIVITY_FLUSH_MS (default 1000). In test mode records are written
+// synchronously, so recordings can read them.
+
+use chrono::{DateTime, Utc};
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "postgres", derive(Insertable, Queryable, QueryableByName))]
+#[cfg_attr(feature = "postgres", table_name = "realm_activity")]
+pub struct Record {
+    pub url: String,
+    pub method: String,
+    pub ua: String,
+    pub ip: String,
+    pub okind: String,
+    pub oid: String,
+    pub ekind: String,
+    pub data: serde_json::Value,
+    pub uid: Option<String>,
+    pub sid: Option<String>,
+    pub vid: String,
+    pub vid_created: bool,
+    pub tid: String,
+    pub tid_created: bool,
+    pub when: DateTime<Utc>,
+    pub duration: i32,
+    pub response: serde_json::Value,
+    pub outcome: String,
+    pub code: String,
+    pub trace: serde_json::Value,
+    pub hash: String,
+    pub rust_trace: Option<String>,
+    pub utm_source: Option<String>,
+    pub utm_medium: Option<String>,
+    pub utm_campaign: Option<String>,
+    pub utm_term: Option<String>,
+    pub utm_content: Option<String>,
+    pub site_version: String,
+}
+
+#[cfg(feature = "postgres")]
+use crate::schema::realm_activity;
+
+pub trait ActivitySink: Send + Sync {
+    fn write(&self, records: &[Record]) -> Result<(), failure::Error>;
+}
+
+#[cfg(feature = "postgres")]
+pub struct PostgresSink;
+
+#[cfg(feature = "postgres")]
+impl ActivitySink for PostgresSink {
+    fn write(&self, records: &[Record]) -> Result<(), failure::Error> {
+        use diesel::RunQueryDsl;
+
+        diesel::insert_into(realm_activity::table)
+            .values(records)
+            .execute(&*crate::base::pg::try_connection()?)?;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "sqlite")]
+mod sqlite_schema {
+    // sqlite has no jsonb, json columns are stored as text
+    table! {
+        realm_activity (id) {
+            id -> Integer,
+            url -> Text,
+            method -> Text,
+            ua -> Text,
+            ip -> Text,
+            okind -> Text,
+            oid -> Text,
+            ekind -> Text,
+            data -> Text,
+            uid -> Nullable<Text>,
+            sid -> Nullable<Text>,
+            vid -> Text,
+            vid_created -> Bool,
+            tid -> Text,
+            tid_created -> Bool,
+            when -> Timestamp,
+            duration -> Integer,
+            response -> Text,
+            outcome -> Text,
+            code -> Text,
+            trace -> Text,
+            hash -> Text,
+            rust_trace -> Nullable<Text>,
+            utm_source -> Nullable<Text>,
+            utm_medium -> Nullable<Text>,
+            utm_campaign -> Nullable<Text>,
+            utm_term -> Nullable<Text>,
+            utm_content -> Nullable<Text>,
+            site_version -> Text,
+        }
+    }
+}
+
+#[cfg(feature = "sqlite")]
+pub struct SqliteSink;
+
+#[cfg(feature = "sqlite")]
+impl ActivitySink for SqliteSink {
+    fn write(&self, records: &[Record]) -> Result<(), failure::Error> {
+        use diesel::{Connection, ExpressionMethods, RunQueryDsl};
+        use sqlite_schema::realm_activity as a;
+
+        let conn = crate::base::sqlite::connection()?;
+        conn.transaction::<_, failure::Error, _>(|| {
+            for r in records.iter() {
+                diesel::insert_into(a::table)
+                    .values((
+                        a::url.eq(&r.url),
+                        a::method.eq(&r.method),
+                        a::ua.eq(&r.ua),
+                        a::ip.eq(&r.ip),
+                        a::okind.eq(&r.okind),
+                        a::oid.eq(&r.oid),
+                        a::ekind.eq(&r.ekind),
+                        a::data.eq(r.data.to_string()),
+                        a::uid.eq(&r.uid),
+                        a::sid.eq(&r.sid),
+                        a::vid.eq(&r.vid),
+                        a::vid_created.eq(r.vid_created),
+                        a::tid.eq(&r.tid),
+                        a::tid_created.eq(r.tid_created),
+                        a::when.eq(r.when.naive_utc()),
+                        a::duration.eq(r.duration),
+                        a::response.eq(r.response.to_string()),
+                        a::outcome.eq(&r.outcome),
+                        a::code.eq(&r.code),
+                        a::trace.eq(r.trace.to_string()),
+                        a::hash.eq(&r.hash),
+                        a::rust_trace.eq(&r.rust_trace),
+                        a::utm_source.eq(&r.utm_source),
+                        a::utm_medium.eq(&r.utm_medium),
+                        a::utm_campaign.eq(&r.utm_campaign),
+                        a::utm_term.eq(&r.utm_term),
+                        a::utm_content.eq(&r.utm_content),
+                        a::site_version.eq(&r.site_version),
+                    ))
+                    .execute(&conn)?;
+            }
+            Ok(())
+        })
+    }
+}
+
+pub struct JsonlSink {
+    pub path: std::path::PathBuf,
+}
+
+impl ActivitySink for JsonlSink {
+    fn write(&self, records: &[Record]) -> Result<(), failure::Error> {
+        use std::io::Write;
+
+        let mut lines = vec![];
+        for r in records.iter() {
+            serde_json::to_writer(&mut lines, r)?;
+            lines.push(b'\n');
+        }
+
+        std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?
+            .write_all(lines.as_slice())?;
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+pub struct MemorySink {
+    records: antidote::Mutex<Vec<Record>>,
+}
+
+impl MemorySink {
+    pub fn records(&self) -> Vec<Record> {
+        self.records.lock().clone()
+    }
+
+    pub fn clear(&self) {
+        self.records.lock().clear()
+    }
+}
+
+impl ActivitySink for MemorySink {
+    fn write(&self, records: &[Record]) -> Result<(), failure::Error> {
+        self.records.lock().extend_from_slice(records);
+        Ok(())
+    }
+}
+
+pub struct NoSink;
+
+impl ActivitySink for NoSink {
+    fn write(&self, _records: &[Record]) -> Result<(), failure::Error> {
+        Ok(())
+    }
+}
+
+// memory sink used when REALM_ACTIVITY_SINK=memory
+lazy_static! {
+    pub static ref MEMORY: std::sync::Arc<MemorySink> = std::sync::Arc::new(Default::default());
+}
+
+fn from_env() -> Result<std::sync::Arc<dyn ActivitySink>, failure::Error> {
+    let sink = std::env::var("REALM_ACTIVITY_SINK").unwrap_or_else(|_| "postgres".to_string());
+    Ok(match sink.as_str() {
+        #[cfg(feature = "postgres")]
+        "postgres" => std::sync::Arc::new(PostgresSink),
+        #[cfg(feature = "sqlite")]
+        "sqlite" => std::sync::Arc::new(SqliteSink),
+        "memory" => MEMORY.clone(),
+        "none" | "" => std::sync::Arc::new(NoSink),
+        t if t.starts_with("jsonl:") => std::sync::Arc::new(JsonlSink {
+            path: std::path::PathBuf::from(&t["jsonl:".len()..]),
+        }),
+        t => {
+            return Err(format_err!(
+                "REALM_ACTIVITY_SINK: unknown or not enabled sink: {}",
+                t
+            ))
+        }
+    })
+}
+
+// called by realm::env::init(), a sink set() before it is kept
+pub fn init() -> Result<(), failure::Error> {
+    if SINK.read().is_none() {
+        set(from_env()?);
+    }
+    Ok(())
+}
+
+enum Message {
+    Record(Box<Record>),
+    Flush(crossbeam_channel::Sender<()>),
+}
+
+lazy_static! {
+    static ref SINK: antidote::RwLock<Option<std::sync::Arc<dyn ActivitySink>>> =
+        antidote::RwLock::new(None);
+    static ref QUEUE: crossbeam_channel::Sender<Message> = start();
+}
+
+pub fn set(sink: std::sync::Arc<dyn ActivitySink>) {
+    *SINK.write() = Some(sink);
+}
+
+fn sink() -> std::sync::Arc<dyn ActivitySink> {
+    if let Some(ref s) = *SINK.read() {
+        return s.clone();
+    }
+
+    let mut s = SINK.write();
+    s.get_or_insert_with(|| {
+        from_env().unwrap_or_else(|e| {
+            eprintln!("realm::activity_sink: {}, activity is not stored", e);
+            std::sync::Arc::new(NoSink)
+        })
+    })
+    .clone()
+}
+
+fn write(records: &[Record]) {
+    if records.is_empty() {
+        return;
+    }
+    // a panicking sink must not take the sink thread down with it
+    match std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| sink().write(records))) {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => eprintln!(
+            "realm::activity_sink: failed to write {} records: {}",
+            records.len(),
+            e
+        ),
+        Err(_) => eprintln!(
+            "realm::activity_sink: sink panicked, {} records dropped",
+            records.len()
+        ),
+    }
+}
+
+fn start() -> crossbeam_channel::Sender<Message> {
+    let (sender, receiver) = crossbeam_channel::bounded(*crate::env::REALM_ACTIVITY_QUEUE_SIZE);
+    let batch_size = *crate::env::REALM_ACTIVITY_BATCH_SIZE;
+    let interval = std::time::Duration::from_millis(*crate::env::REALM_ACTIVITY_FLUSH_MS);
+
+    std::thread::Builder::new()
+        .name("realm-activity-sink".to_string())
+        .spawn(move || {
+            let mut batch: Vec<Record> = Vec::with_capacity(batch_size);
+            let mut last = std::time::Instant::now();
+            loop {
+                match receiver.recv_timeout(interval) {
+                    Ok(Message::Record(r)) => batch.push(*r),
+                    Ok(Message::Flush(done)) => {
+                        write(&batch);
+                        batch.clear();
+                        last = std::time::Instant::now();
+                        let _ = done.send(());
+                        continue;
+                    }
+                    Err(crossbeam_channel::RecvTimeoutError::Timeout) => {}
+                    Err(crossbeam_channel::RecvTimeoutError::Disconnected) => {
+                        write(&batch);
+                        return;
+                    }
+                }
+
+                if batch.len() >= batch_size || last.elapsed() >= interval {
+                    write(&batch);
+                    batch.clear();
+                    last = std::time::Instant::now();
+                }
+            }
+        })
+        .expect("failed to start activity sink thread");
+
+    sender
+}
+
+pub fn store(record: Record) {
+    if crate::base::is_test() {
+        write(&[record]);
+        return;
+    }
+
+    if QUEUE.try_send(Message::Record(Box::new(record))).is_err() {
+        eprintln!("realm::activity_sink: queue full, activity dropped");
+    }
+}
+
+// blocks till all queued records are written, call before exiting
+pub fn flush() {
+    let (done, wait) = crossbeam_channel::bounded(1);
+    if QUEUE.send(Message::Flush(done)).is_ok() {
+        let _ = wait.recv();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn jsonl() {
+        let path = std::env::temp_dir().join(format!(
+            "realm-activity-{}.jsonl",
+            crate::utils::get_random_alphanumeric_string(8)
+        ));
+        let sink = super::JsonlSink { path: path.clone() };
+
+        let record = super::Record {
+            url: "/".to_string(),
+            method: "GET".to_string(),
+            ua: "".to_string(),
+            ip: "".to_string(),
+            okind: "page".to_string(),
+            oid: "".to_string(),
+            ekind: "view".to_string(),
+            data: json!({"lang": "en"}),
+            uid: None,
+            sid: None,
+            vid: "v".to_string(),
+            vid_created: false,
+            tid: "t".to_string(),
+            tid_created: true,
+            when: chrono::Utc::now(),
+            duration: 10,
+            response: serde_json::Value::Null,
+            outcome: "success".to_string(),
+            code: "success".to_string(),
+            trace: serde_json::Value::Null,
+            hash: "".to_string(),
+            rust_trace: None,
+            utm_source: None,
+            utm_medium: None,
+            utm_campaign: None,
+            utm_term: None,
+            utm_content: None,
+            site_version: "".to_string(),
+        };
+
+        super::ActivitySink::write(&sink, &[record.clone(), record.clone()]).unwrap();
+        super::ActivitySink::write(&sink, &[record.clone()]).unwrap();
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let lines: Vec<super::Record> = content
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(lines, vec![record.clone(), record.clone(), record]);
+    }
+}
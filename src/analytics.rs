@@ -0,0 +1,405 @@
+// Visitor analytics from realm_activity. A tid cookie lives for years, so it
+// stands for a visitor (a browser really), a vid cookie expires after some
+// inactivity, so it stands for a session.
+//
+// - summary() and daily(): unique visitors, new visitors, sessions, requests
+// - attribution(): first-touch and last-touch UTM source/medium/campaign of
+//   visitors seen in the range, tid_attribution() for one visitor
+// - funnel(): how many visitors did the okind/ekind steps in order
+//
+// /realm/analytics/?from=2021-02-01&to=2021-02-28&funnel=article:view,user:signup
+// shows all of them. It is available in dev mode and to users whose uid is in
+// REALM_ANALYTICS_UIDS (comma separated).
+
+use chrono::{DateTime, Duration, NaiveDate, TimeZone, Utc};
+use diesel::prelude::*;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Range {
+    // inclusive
+    pub from: DateTime<Utc>,
+    // exclusive
+    pub to: DateTime<Utc>,
+}
+
+impl Range {
+    // both days included
+    pub fn days(from: NaiveDate, to: NaiveDate) -> Self {
+        Range {
+            from: Utc.from_utc_date(&from).and_hms(0, 0, 0),
+            to: Utc.from_utc_date(&to).and_hms(0, 0, 0) + Duration::days(1),
+        }
+    }
+
+    pub fn last_days(days: i64) -> Self {
+        let today = Utc::today().naive_utc();
+        Range::days(today - Duration::days(days - 1), today)
+    }
+}
+
+#[derive(QueryableByName, Debug, PartialEq, serde::Serialize)]
+pub struct Summary {
+    #[sql_type = "diesel::sql_types::BigInt"]
+    pub visitors: i64,
+    #[sql_type = "diesel::sql_types::BigInt"]
+    pub new_visitors: i64,
+    #[sql_type = "diesel::sql_types::BigInt"]
+    pub sessions: i64,
+    #[sql_type = "diesel::sql_types::BigInt"]
+    pub requests: i64,
+}
+
+#[derive(QueryableByName, Debug, PartialEq, serde::Serialize)]
+pub struct Day {
+    #[sql_type = "diesel::sql_types::Date"]
+    pub day: NaiveDate,
+    #[sql_type = "diesel::sql_types::BigInt"]
+    pub visitors: i64,
+    #[sql_type = "diesel::sql_types::BigInt"]
+    pub new_visitors: i64,
+    #[sql_type = "diesel::sql_types::BigInt"]
+    pub sessions: i64,
+    #[sql_type = "diesel::sql_types::BigInt"]
+    pub requests: i64,
+}
+
+#[derive(QueryableByName, Debug, PartialEq, serde::Serialize)]
+pub struct Attribution {
+    #[sql_type = "diesel::sql_types::Text"]
+    pub source: String,
+    #[sql_type = "diesel::sql_types::Text"]
+    pub medium: String,
+    #[sql_type = "diesel::sql_types::Text"]
+    pub campaign: String,
+    // visitors whose first utm tagged request had this source/medium/campaign
+    #[sql_type = "diesel::sql_types::BigInt"]
+    pub first_touch: i64,
+    #[sql_type = "diesel::sql_types::BigInt"]
+    pub last_touch: i64,
+}
+
+#[derive(QueryableByName, Debug, Clone, PartialEq, serde::Serialize)]
+pub struct Touch {
+    #[sql_type = "diesel::sql_types::Timestamptz"]
+    pub when: DateTime<Utc>,
+    #[sql_type = "diesel::sql_types::Text"]
+    pub source: String,
+    #[sql_type = "diesel::sql_types::Nullable<diesel::sql_types::Text>"]
+    pub medium: Option<String>,
+    #[sql_type = "diesel::sql_types::Nullable<diesel::sql_types::Text>"]
+    pub campaign: Option<String>,
+    #[sql_type = "diesel::sql_types::Nullable<diesel::sql_types::Text>"]
+    pub term: Option<String>,
+    #[sql_type = "diesel::sql_types::Nullable<diesel::sql_types::Text>"]
+    pub content: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct Step {
+    pub okind: String,
+    pub ekind: String,
+}
+
+impl std::str::FromStr for Step {
+    type Err = failure::Error;
+
+    // okind:ekind
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.trim().splitn(2, ':');
+        match (parts.next(), parts.next()) {
+            (Some(okind), Some(ekind)) if !okind.is_empty() && !ekind.is_empty() => Ok(Step {
+                okind: okind.to_string(),
+                ekind: ekind.to_string(),
+            }),
+            _ => Err(format_err!("funnel step must be okind:ekind, found: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, serde::Serialize)]
+pub struct FunnelStep {
+    pub step: Step,
+    // visitors who did this step after doing all previous ones
+    pub visitors: i64,
+}
+
+const COUNTS: &str =
+    "\
+     count(DISTINCT tid) FILTER (WHERE tid <> '') AS visitors, \
+     count(DISTINCT tid) FILTER (WHERE tid <> '' AND tid_created) AS new_visitors, \
+     count(DISTINCT vid) FILTER (WHERE vid <> '') AS sessions, \
+     count(*) AS requests";
+
+pub fn summary(
+    conn: &crate::base::pg::RealmConnection,
+    range: Range,
+) -> crate::base::Result<Summary> {
+    diesel::sql_query(format!(
+        "SELECT {} FROM realm_activity WHERE \"when\" >= $1 AND \"when\" < $2",
+        COUNTS
+    ))
+    .bind::<diesel::sql_types::Timestamptz, _>(range.from)
+    .bind::<diesel::sql_types::Timestamptz, _>(range.to)
+    .get_result(conn)
+    .map_err(Into::into)
+}
+
+pub fn daily(
+    conn: &crate::base::pg::RealmConnection,
+    range: Range,
+) -> crate::base::Result<Vec<Day>> {
+    diesel::sql_query(format!(
+        "SELECT (\"when\" AT TIME ZONE 'UTC')::date AS day, {} \
+         FROM realm_activity WHERE \"when\" >= $1 AND \"when\" < $2 \
+         GROUP BY 1 ORDER BY 1",
+        COUNTS
+    ))
+    .bind::<diesel::sql_types::Timestamptz, _>(range.from)
+    .bind::<diesel::sql_types::Timestamptz, _>(range.to)
+    .load(conn)
+    .map_err(Into::into)
+}
+
+// touches are utm tagged requests of visitors seen in range, till range end
+pub fn attribution(
+    conn: &crate::base::pg::RealmConnection,
+    range: Range,
+) -> crate::base::Result<Vec<Attribution>> {
+    diesel::sql_query(
+        "WITH touches AS ( \
+         SELECT tid, \"when\", utm_source, \
+         coalesce(utm_medium, '') AS utm_medium, \
+         coalesce(utm_campaign, '') AS utm_campaign \
+         FROM realm_activity \
+         WHERE utm_source IS NOT NULL AND \"when\" < $2 AND tid IN ( \
+         SELECT DISTINCT tid FROM realm_activity \
+         WHERE \"when\" >= $1 AND \"when\" < $2 AND tid <> '' \
+         ) \
+         ), first_touches AS ( \
+         SELECT DISTINCT ON (tid) * FROM touches ORDER BY tid, \"when\" ASC \
+         ), last_touches AS ( \
+         SELECT DISTINCT ON (tid) * FROM touches ORDER BY tid, \"when\" DESC \
+         ) \
+         SELECT \
+         utm_source AS source, utm_medium AS medium, utm_campaign AS campaign, \
+         sum(first_touch)::bigint AS first_touch, sum(last_touch)::bigint AS last_touch \
+         FROM ( \
+         SELECT utm_source, utm_medium, utm_campaign, 1 AS first_touch, 0 AS last_touch \
+         FROM first_touches \
+         UNION ALL \
+         SELECT utm_source, utm_medium, utm_campaign, 0, 1 FROM last_touches \
+         ) t \
+         GROUP BY 1, 2, 3 \
+         ORDER BY 4 DESC, 5 DESC",
+    )
+    .bind::<diesel::sql_types::Timestamptz, _>(range.from)
+    .bind::<diesel::sql_types::Timestamptz, _>(range.to)
+    .load(conn)
+    .map_err(Into::into)
+}
+
+// (first touch, last touch) of one visitor
+pub fn tid_attribution(
+    conn: &crate::base::pg::RealmConnection,
+    tid: &str,
+) -> crate::base::Result<(Option<Touch>, Option<Touch>)> {
+    let touches: Vec<Touch> = diesel::sql_query(
+        "SELECT \"when\", utm_source AS source, utm_medium AS medium, \
+         utm_campaign AS campaign, utm_term AS term, utm_content AS content \
+         FROM realm_activity \
+         WHERE tid = $1 AND utm_source IS NOT NULL \
+         ORDER BY \"when\"",
+    )
+    .bind::<diesel::sql_types::Text, _>(tid)
+    .load(conn)?;
+
+    Ok((touches.first().cloned(), touches.last().cloned()))
+}
+
+#[derive(QueryableByName)]
+struct Reached {
+    // 1 based index of the step
+    #[sql_type = "diesel::sql_types::Integer"]
+    n: i32,
+    #[sql_type = "diesel::sql_types::BigInt"]
+    visitors: i64,
+}
+
+// visitors by step, steps no one reached are 0
+fn step_counts(reached: &[(i32, i64)], steps: usize) -> Vec<i64> {
+    let mut counts = vec![0; steps];
+    for (n, visitors) in reached.iter() {
+        if *n >= 1 && (*n as usize) <= steps {
+            counts[*n as usize - 1] = *visitors;
+        }
+    }
+    counts
+}
+
+// every visitor walks the steps in postgres, each step is the first matching
+// event after the previous one, so only one row per visitor and step is read
+pub fn funnel(
+    conn: &crate::base::pg::RealmConnection,
+    range: Range,
+    steps: &[Step],
+) -> crate::base::Result<Vec<FunnelStep>> {
+    use diesel::sql_types::{Array, Text, Timestamptz};
+
+    if steps.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let reached: Vec<Reached> = diesel::sql_query(
+        "WITH RECURSIVE progress AS ( \
+         (SELECT DISTINCT ON (tid) tid, \"when\", id, 1 AS n \
+         FROM realm_activity \
+         WHERE \"when\" >= $1 AND \"when\" < $2 AND tid <> '' \
+         AND okind = $3[1] AND ekind = $4[1] \
+         ORDER BY tid, \"when\", id) \
+         UNION ALL \
+         SELECT p.tid, e.\"when\", e.id, p.n + 1 \
+         FROM progress p CROSS JOIN LATERAL ( \
+         SELECT \"when\", id FROM realm_activity a \
+         WHERE a.tid = p.tid AND (a.\"when\", a.id) > (p.\"when\", p.id) \
+         AND a.\"when\" < $2 AND a.okind = $3[p.n + 1] AND a.ekind = $4[p.n + 1] \
+         ORDER BY a.\"when\", a.id LIMIT 1 \
+         ) e \
+         WHERE p.n < array_length($3, 1) \
+         ) \
+         SELECT n, count(*) AS visitors FROM progress GROUP BY n",
+    )
+    .bind::<Timestamptz, _>(range.from)
+    .bind::<Timestamptz, _>(range.to)
+    .bind::<Array<Text>, _>(steps.iter().map(|s| s.okind.clone()).collect::<Vec<_>>())
+    .bind::<Array<Text>, _>(steps.iter().map(|s| s.ekind.clone()).collect::<Vec<_>>())
+    .load(conn)?;
+
+    let reached: Vec<(i32, i64)> = reached.into_iter().map(|r| (r.n, r.visitors)).collect();
+    Ok(steps
+        .iter()
+        .cloned()
+        .zip(step_counts(&reached, steps.len()).into_iter())
+        .map(|(step, visitors)| FunnelStep { step, visitors })
+        .collect())
+}
+
+lazy_static! {
+    static ref UIDS: Vec<String> = std::env::var("REALM_ANALYTICS_UIDS")
+        .unwrap_or_else(|_| "".to_string())
+        .split(',')
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty())
+        .collect();
+}
+
+pub fn is_allowed<UD>(in_: &crate::base::In<UD>) -> bool
+where
+    UD: crate::UserData,
+{
+    in_.is_dev()
+        || in_
+            .user_id()
+            .map(|uid| UIDS.contains(&uid))
+            .unwrap_or(false)
+}
+
+#[derive(askama::Template)]
+#[template(path = "analytics.html")]
+struct Report {
+    from: NaiveDate,
+    to: NaiveDate,
+    funnel_param: String,
+    summary: Summary,
+    daily: Vec<Day>,
+    attribution: Vec<Attribution>,
+    funnel: Vec<FunnelStep>,
+}
+
+fn parse_date(v: Option<String>, default: NaiveDate) -> Result<NaiveDate, crate::Error> {
+    match v {
+        Some(ref v) if !v.trim().is_empty() => NaiveDate::parse_from_str(v.trim(), "%Y-%m-%d")
+            .map_err(|e| crate::Error::CustomError {
+                message: format!("invalid date {}: {}", v, e),
+            }),
+        _ => Ok(default),
+    }
+}
+
+pub fn report<UD>(
+    in_: &crate::base::In<UD>,
+    from: Option<String>,
+    to: Option<String>,
+    funnel_param: Option<String>,
+) -> Result<crate::Response, failure::Error>
+where
+    UD: crate::UserData,
+{
+    use askama::Template;
+
+    if !is_allowed(in_) {
+        return Err(crate::Error::PageNotFound {
+            message: "analytics not allowed".to_string(),
+        }
+        .into());
+    }
+
+    let today = Utc::today().naive_utc();
+    let to = parse_date(to, today)?;
+    let from = parse_date(from, to - Duration::days(29))?;
+    let range = Range::days(from, to);
+
+    let funnel_param = funnel_param.unwrap_or_else(|| "".to_string());
+    let steps = funnel_param
+        .split(',')
+        .filter(|s| !s.trim().is_empty())
+        .map(|s| s.parse())
+        .collect::<Result<Vec<Step>, _>>()?;
+
+    let report = Report {
+        from,
+        to,
+        summary: summary(in_.conn, range)?,
+        daily: daily(in_.conn, range)?,
+        attribution: attribution(in_.conn, range)?,
+        funnel: funnel(in_.conn, range, &steps)?,
+        funnel_param,
+    };
+
+    in_.ctx
+        .header(http::header::CONTENT_TYPE, "text/html; charset=utf-8");
+    Ok(crate::Response::Http(
+        in_.ctx.response(report.render()?.into_bytes())?,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn step() {
+        assert_eq!(
+            "article:view".parse::<super::Step>().unwrap(),
+            super::Step {
+                okind: "article".to_string(),
+                ekind: "view".to_string(),
+            }
+        );
+        assert!("article".parse::<super::Step>().is_err());
+        assert!(":view".parse::<super::Step>().is_err());
+    }
+
+    #[test]
+    fn step_counts() {
+        assert_eq!(super::step_counts(&[(2, 1), (1, 2)], 3), vec![2, 1, 0]);
+        assert_eq!(super::step_counts(&[], 2), vec![0, 0]);
+    }
+
+    #[test]
+    fn range() {
+        let r = super::Range::days(
+            chrono::NaiveDate::from_ymd(2021, 2, 1),
+            chrono::NaiveDate::from_ymd(2021, 2, 28),
+        );
+        assert_eq!(r.from.to_rfc3339(), "2021-02-01T00:00:00+00:00");
+        assert_eq!(r.to.to_rfc3339(), "2021-03-01T00:00:00+00:00");
+    }
+}
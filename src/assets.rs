@@ -0,0 +1,320 @@
+// Every file in static/ gets a content hashed name, eg css/main.css is served as
+// /static/css/main.hashed-0123456789.css, which can be cached forever. The
+// mapping is stored in static/manifest.json, created by running the server
+// with `--build-assets`. In debug builds the manifest is recomputed when a file
+// in static/ is added, removed or modified, so edits are picked up without a
+// restart.
+
+pub const MANIFEST_FILE: &str = "manifest.json";
+const HASH_LENGTH: usize = 10;
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct Manifest {
+    // hash of all the files, used in place of static/current.txt
+    pub version: String,
+    // logical name -> hashed name, both relative to static/
+    pub files: std::collections::BTreeMap<String, String>,
+    // logical name -> subresource integrity, eg sha256-<base64 of digest>
+    #[serde(default)]
+    pub integrity: std::collections::BTreeMap<String, String>,
+}
+
+impl Manifest {
+    pub fn build(dir: &std::path::Path) -> Result<Manifest, failure::Error> {
+        let mut paths = vec![];
+        walk(dir, &mut paths)?;
+
+        let mut files = vec![];
+        for path in paths.into_iter() {
+            let name = path.strip_prefix(dir)?.to_string_lossy().replace('\\', "/");
+            if is_ignored(name.as_str()) {
+                continue;
+            }
+            files.push((name, hash(std::fs::read(&path)?.as_slice())));
+        }
+
+        Ok(Self::from_hashes(files))
+    }
+
+    pub fn from_hashes(hashes: Vec<(String, String)>) -> Manifest {
+        let mut all = vec![];
+        let mut files = std::collections::BTreeMap::new();
+        let mut integrity = std::collections::BTreeMap::new();
+        for (name, hash) in hashes.into_iter() {
+            all.push(format!("{}:{}", name, hash));
+            integrity.insert(name.clone(), hex_to_integrity(hash.as_str()));
+            let hashed = hashed_name(name.as_str(), &hash[..HASH_LENGTH]);
+            files.insert(name, hashed);
+        }
+        all.sort();
+
+        Manifest {
+            version: hash(all.join("\n").as_bytes())[..HASH_LENGTH].to_string(),
+            files,
+            integrity,
+        }
+    }
+
+    // name is relative to static/, eg css/main.css, names not in manifest are
+    // returned as is, so a missing file results in a 404 and not a panic
+    pub fn url(&self, name: &str) -> String {
+        let name = name.trim_start_matches('/');
+        format!(
+            "/static/{}",
+            self.files.get(name).map(String::as_str).unwrap_or(name)
+        )
+    }
+
+    // reverse of url(), returns the path of file on disk for a hashed url, the
+    // pre-compressed siblings are looked up by the name of original file
+    pub fn resolve(&self, url: &str) -> Option<String> {
+        let hashed = url.trim_start_matches("/static/");
+        if !hashed.contains(".hashed-") {
+            return None;
+        }
+
+        let (hashed, suffix) = [".br", ".gz"]
+            .iter()
+            .find(|s| hashed.ends_with(*s))
+            .map(|s| (&hashed[..hashed.len() - s.len()], *s))
+            .unwrap_or((hashed, ""));
+
+        self.files
+            .iter()
+            .find(|(_, h)| h.as_str() == hashed)
+            .map(|(name, _)| format!("/static/{}{}", name, suffix))
+    }
+
+    // url can be the hashed or the logical url of the file
+    pub fn integrity_for(&self, url: &str) -> Option<&str> {
+        let url = url.split('?').next().unwrap_or(url);
+        if !url.starts_with("/static/") {
+            return None;
+        }
+
+        let name = match self.resolve(url) {
+            Some(resolved) => resolved,
+            None => url.to_string(),
+        };
+        self.integrity
+            .get(name.trim_start_matches("/static/"))
+            .map(String::as_str)
+    }
+}
+
+fn hex_to_integrity(hex: &str) -> String {
+    let bytes: Vec<u8> = (0..hex.len() / 2)
+        .filter_map(|i| u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).ok())
+        .collect();
+    format!("sha256-{}", base64::encode(&bytes))
+}
+
+fn is_ignored(name: &str) -> bool {
+    name == MANIFEST_FILE
+        || name == "current.txt"
+        || name.contains(".hashed-")
+        || name.ends_with(".br")
+        || name.ends_with(".gz")
+}
+
+fn walk(dir: &std::path::Path, files: &mut Vec<std::path::PathBuf>) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            walk(&path, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+pub fn hash(content: &[u8]) -> String {
+    use sha2::Digest;
+
+    format!("{:x}", sha2::Sha256::digest(content))
+}
+
+pub fn hashed_name(name: &str, hash: &str) -> String {
+    let (dir, file) = match name.rfind('/') {
+        Some(i) => (&name[..=i], &name[i + 1..]),
+        None => ("", name),
+    };
+
+    match file.find('.') {
+        Some(i) if i > 0 => format!("{}{}.hashed-{}{}", dir, &file[..i], hash, &file[i..]),
+        _ => format!("{}{}.hashed-{}", dir, file, hash),
+    }
+}
+
+pub fn write(dir: &std::path::Path) -> Result<Manifest, failure::Error> {
+    let manifest = Manifest::build(dir)?;
+    std::fs::write(
+        dir.join(MANIFEST_FILE),
+        serde_json::to_string_pretty(&manifest)?,
+    )?;
+    Ok(manifest)
+}
+
+fn read_manifest() -> Result<Manifest, failure::Error> {
+    if crate::embed::is_embedded() {
+        return match crate::embed::get(&format!("/static/{}", MANIFEST_FILE)) {
+            Some(m) => serde_json::from_slice(m.content)
+                .map_err(|e| format_err!("invalid embedded manifest.json: {}", e)),
+            None => Ok(Manifest::from_hashes(
+                crate::embed::files()
+                    .iter()
+                    .filter(|a| a.path.starts_with("/static/"))
+                    .map(|a| (a.path.trim_start_matches("/static/").to_string(), a))
+                    .filter(|(name, _)| !is_ignored(name.as_str()))
+                    .map(|(name, a)| (name, a.hash.to_string()))
+                    .collect(),
+            )),
+        };
+    }
+
+    let dir = static_dir();
+    match std::fs::read(dir.join(MANIFEST_FILE)) {
+        Ok(m) if !cfg!(debug_assertions) => serde_json::from_slice(m.as_slice())
+            .map_err(|e| format_err!("invalid static/{}: {}", MANIFEST_FILE, e)),
+        _ => Ok(Manifest::build(&dir).unwrap_or_else(|e| {
+            println!("failed to build asset manifest: {}", e);
+            Manifest::default()
+        })),
+    }
+}
+
+fn static_dir() -> std::path::PathBuf {
+    std::path::PathBuf::from("static")
+}
+
+// number of files, their total size and latest modification time
+type Fingerprint = (usize, u64, Option<std::time::SystemTime>);
+
+lazy_static! {
+    static ref MANIFEST: Result<std::sync::Arc<Manifest>, failure::Error> =
+        read_manifest().map(std::sync::Arc::new);
+    static ref DEV_MANIFEST: antidote::Mutex<Option<(Fingerprint, std::sync::Arc<Manifest>)>> =
+        antidote::Mutex::new(None);
+}
+
+// only reads metadata, much cheaper than hashing the content
+fn fingerprint(dir: &std::path::Path) -> Fingerprint {
+    let mut paths = vec![];
+    let _ = walk(dir, &mut paths);
+
+    let (mut size, mut modified) = (0, None);
+    for path in paths.iter() {
+        if let Ok(m) = std::fs::metadata(path) {
+            size += m.len();
+            modified = std::cmp::max(modified, m.modified().ok());
+        }
+    }
+    (paths.len(), size, modified)
+}
+
+pub fn get_manifest() -> std::sync::Arc<Manifest> {
+    if !cfg!(debug_assertions) || crate::embed::is_embedded() {
+        // a bad manifest is reported by init() at startup
+        return MANIFEST.as_ref().ok().cloned().unwrap_or_default();
+    }
+
+    let current = fingerprint(&static_dir());
+    let mut cached = DEV_MANIFEST.lock();
+    if let Some((ref f, ref m)) = *cached {
+        if *f == current {
+            return m.clone();
+        }
+    }
+
+    let m = std::sync::Arc::new(read_manifest().unwrap_or_else(|e| {
+        println!("{}", e);
+        Manifest::default()
+    }));
+    *cached = Some((current, m.clone()));
+    m
+}
+
+// called by realm::env::init()
+pub fn init() -> Result<(), failure::Error> {
+    match *MANIFEST {
+        Ok(_) => Ok(()),
+        Err(ref e) => Err(format_err!("{}", e)),
+    }
+}
+
+pub fn url(name: &str) -> String {
+    get_manifest().url(name)
+}
+
+// askama filter, to use it in templates add the following next to the struct
+// deriving Template:
+//
+//     mod filters {
+//         pub use realm::assets::asset;
+//     }
+//
+// and use it as: <link rel="stylesheet" href="{{ "css/main.css"|asset }}">
+pub fn asset<T: std::fmt::Display>(name: T) -> askama::Result<String> {
+    Ok(url(name.to_string().as_str()))
+}
+
+pub fn build_assets() {
+    let dir = static_dir();
+    match write(&dir) {
+        Ok(m) => println!(
+            "wrote {:?}, {} files, version: {}",
+            dir.join(MANIFEST_FILE),
+            m.files.len(),
+            m.version
+        ),
+        Err(e) => println!("failed to build assets: {:?}", e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn hashed_name() {
+        assert_eq!(
+            super::hashed_name("elm.js", "abc"),
+            "elm.hashed-abc.js".to_string()
+        );
+        assert_eq!(
+            super::hashed_name("css/main.min.css", "abc"),
+            "css/main.hashed-abc.min.css".to_string()
+        );
+        assert_eq!(
+            super::hashed_name("LICENSE", "abc"),
+            "LICENSE.hashed-abc".to_string()
+        );
+        assert_eq!(
+            super::hashed_name("a.b/.env", "abc"),
+            "a.b/.env.hashed-abc".to_string()
+        );
+    }
+
+    #[test]
+    fn url_and_resolve() {
+        let m = super::Manifest::from_hashes(vec![(
+            "css/main.css".to_string(),
+            "0123456789abcdef".to_string(),
+        )]);
+
+        assert_eq!(
+            m.url("css/main.css"),
+            "/static/css/main.hashed-0123456789.css"
+        );
+        assert_eq!(m.url("/css/other.css"), "/static/css/other.css");
+        assert_eq!(
+            m.resolve("/static/css/main.hashed-0123456789.css"),
+            Some("/static/css/main.css".to_string())
+        );
+        assert_eq!(
+            m.resolve("/static/css/main.hashed-0123456789.css.br"),
+            Some("/static/css/main.css.br".to_string())
+        );
+        assert_eq!(m.resolve("/static/css/main.css"), None);
+        assert_eq!(m.resolve("/static/css/main.hashed-9999999999.css"), None);
+    }
+}
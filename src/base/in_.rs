@@ -11,7 +11,6 @@ where
     UD: crate::UserData,
 {
     pub ctx: &'a crate::Context,
-    lang: RefCell<realm_lang::Language>,
     pub head: RefCell<http::response::Builder>,
     ud: RefCell<Option<UD>>,
     #[cfg(any(feature = "sqlite_default", feature = "postgres_default"))]
@@ -24,11 +23,10 @@ where
     vid: RefCell<Option<String>>,
     vid_created: RefCell<bool>,
 
-    // activity stuff
-    okind: RefCell<String>,
-    oid: RefCell<String>,
-    ekind: RefCell<String>,
-    pub activity_data: RefCell<Vec<(String, serde_json::Value)>>,
+    activity: RefCell<crate::Activity>,
+
+    // a task that can run right away was created, see worker::create_realm_task()
+    pub(crate) task_created: RefCell<bool>,
 }
 
 #[allow(clippy::upper_case_acronyms)]
@@ -89,12 +87,6 @@ where
 
         In {
             ctx,
-            lang: RefCell::new(realm_lang::Language::from_accept_language_header(
-                ctx.get_cookie("realm-lang")
-                    .map(|v| v.to_string())
-                    .or_else(|| ctx.get_header_string(http::header::ACCEPT_LANGUAGE)),
-                *crate::env::REALM_LANG,
-            )),
             head: RefCell::new(http::response::Builder::new()),
             ud: RefCell::new(ud),
             conn,
@@ -105,10 +97,9 @@ where
             vid: RefCell::new(vid),
             vid_created: RefCell::new(false),
 
-            okind: RefCell::new("".to_string()),
-            oid: RefCell::new("".to_string()),
-            ekind: RefCell::new("".to_string()),
-            activity_data: RefCell::new(vec![]),
+            activity: RefCell::new(Default::default()),
+
+            task_created: RefCell::new(false),
         }
     }
 
@@ -172,7 +163,7 @@ where
     }
 
     pub fn lang(&self) -> realm_lang::Language {
-        *self.lang.borrow()
+        self.ctx.lang()
     }
 
     pub fn user_id(&self) -> Option<String> {
@@ -184,37 +175,28 @@ where
     }
 
     pub fn activity(&self, okind: &str, oid: &str, ekind: &str) {
-        self.okind.replace(okind.to_string());
-        self.oid.replace(oid.to_string());
-        self.ekind.replace(ekind.to_string());
+        let mut activity = self.activity.borrow_mut();
+        activity.okind = okind.to_string();
+        activity.oid = oid.to_string();
+        activity.ekind = ekind.to_string();
     }
 
-    pub(crate) fn get_activity(&self) -> crate::rr::Activity {
-        crate::rr::Activity {
-            oid: self.oid.borrow().to_owned(),
-            okind: self.okind.borrow().to_owned(),
-            ekind: self.ekind.borrow().to_owned(),
-            data: serde_json::Value::Null,
-        }
+    pub(crate) fn get_activity(&self) -> crate::Activity {
+        self.activity.borrow().clone()
     }
 
     pub fn activity_ekind(&self, ekind: &str) {
-        self.ekind.replace(ekind.to_string());
-    }
-
-    // pub fn activity_data(&self, key: &str, value: serde_json::Value) {
-    //     use std::ops::DerefMut;
-    //
-    //     let mut v1 = self.adata.borrow_mut();
-    //     let v = v1.deref_mut();
-    //     let item = (key.to_string(), value);
-    //     match v {
-    //         Some(a) => a.push(item),
-    //         None => {
-    //             *v = Some(vec![item]);
-    //         }
-    //     }
-    // }
+        self.activity.borrow_mut().ekind = ekind.to_string();
+    }
+
+    pub fn activity_data(&self, key: &str, value: serde_json::Value) {
+        self.activity.borrow_mut().add_data(key, value);
+    }
+
+    // activity returned by the page, eg Page::with_activity()
+    pub(crate) fn merge_activity(&self, other: &crate::Activity) {
+        self.activity.borrow_mut().merge(other);
+    }
 
     pub fn set_ud(&self, ud: UD) {
         self.ctx
@@ -224,7 +206,7 @@ where
 
     pub fn set_lang(&self, lang: realm_lang::Language) {
         self.ctx.cookie("realm-lang", lang.id(), DECADE);
-        self.lang.replace(lang);
+        self.ctx.set_lang(lang);
     }
 
     pub fn set_tid(&self, tid: String) {
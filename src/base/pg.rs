@@ -4,7 +4,7 @@ use diesel::prelude::*;
 
 fn _connection_pool(
     mut db_url: String,
-) -> r2d2::Pool<r2d2_diesel::ConnectionManager<RealmConnection>> {
+) -> Result<r2d2::Pool<r2d2_diesel::ConnectionManager<RealmConnection>>, r2d2::Error> {
     if crate::base::is_test() {
         // add search_path=test (%3D is = sign)
         if db_url.contains('?') {
@@ -23,7 +23,6 @@ fn _connection_pool(
                 .unwrap(),
         )
         .build(manager)
-        .expect("Failed to create DIESEL_POOL.")
 }
 
 lazy_static! {
@@ -50,28 +49,43 @@ pub fn connection_with_url(
 pub fn connection_with_url_(
     db_url: String,
 ) -> r2d2::PooledConnection<r2d2_diesel::ConnectionManager<RealmConnection>> {
+    try_connection_with_url(db_url).unwrap()
+}
+
+// like connection() but returns the error instead of panicking, for code
+// running outside of a request, eg background threads
+pub fn try_connection(
+) -> Result<r2d2::PooledConnection<r2d2_diesel::ConnectionManager<RealmConnection>>, failure::Error>
+{
+    try_connection_with_url(
+        std::env::var("DATABASE_URL").map_err(|_| format_err!("DATABASE_URL not set"))?,
+    )
+}
+
+pub fn try_connection_with_url(
+    db_url: String,
+) -> Result<r2d2::PooledConnection<r2d2_diesel::ConnectionManager<RealmConnection>>, failure::Error>
+{
     {
         if let Some(pool) = DIESEL_POOLS.read().get(&db_url) {
-            return pool
-                .get()
-                .map_err(|e| {
-                    observer::observe_string(
-                        "get_connection_error",
-                        &format!("url: {}, error: {}", db_url, e),
-                    );
-                    e
-                })
-                .unwrap();
+            return Ok(pool.get().map_err(|e| {
+                observer::observe_string(
+                    "get_connection_error",
+                    &format!("url: {}, error: {}", db_url, e),
+                );
+                e
+            })?);
         }
     }
     match DIESEL_POOLS.write().entry(db_url.clone()) {
         std::collections::hash_map::Entry::Vacant(e) => {
-            let conn_pool = _connection_pool(db_url);
-            let conn = conn_pool.get().unwrap();
+            let conn_pool = _connection_pool(db_url)
+                .map_err(|e| format_err!("failed to create DIESEL_POOL: {}", e))?;
+            let conn = conn_pool.get()?;
             e.insert(conn_pool);
-            conn
+            Ok(conn)
         }
-        std::collections::hash_map::Entry::Occupied(e) => e.get().get().unwrap(),
+        std::collections::hash_map::Entry::Occupied(e) => Ok(e.get().get()?),
     }
 }
 
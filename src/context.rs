@@ -14,6 +14,8 @@ pub struct Context {
     step: std::cell::RefCell<Option<crate::rr::Step>>,
     is_test: bool,
     meta: std::cell::RefCell<crate::HTMLMeta>,
+    nonce: String,
+    lang: std::cell::RefCell<Option<realm_lang::Language>>,
 }
 
 pub fn cookies_from_request(
@@ -57,6 +59,8 @@ impl Context {
             record: None,
             step: std::cell::RefCell::new(None),
             is_test: true,
+            nonce: crate::security::new_nonce(),
+            lang: std::cell::RefCell::new(None),
         }
     }
 
@@ -64,6 +68,41 @@ impl Context {
         self.meta.borrow_mut()
     }
 
+    // merges html-meta sections of ftd/p1 content, eg a CMS page, into meta()
+    pub fn merge_meta_p1(
+        &self,
+        sections: &[ftd::p1::Section],
+    ) -> Result<(), crate::html_meta::HTMLMetaError> {
+        for section in sections.iter().filter(|s| s.name == "html-meta") {
+            self.meta().merge(&crate::HTMLMeta::from_p1(section)?);
+        }
+        Ok(())
+    }
+
+    // used for Content-Security-Policy, all script tags on the page get it
+    pub fn nonce(&self) -> &str {
+        self.nonce.as_str()
+    }
+
+    // language of the request, picked from realm-lang cookie, Accept-Language
+    // header or REALM_LANG, in that order, unless overwritten by In::set_lang()
+    pub fn lang(&self) -> realm_lang::Language {
+        if let Some(lang) = *self.lang.borrow() {
+            return lang;
+        }
+
+        realm_lang::Language::from_accept_language_header(
+            self.get_cookie("realm-lang")
+                .map(|v| v.to_string())
+                .or_else(|| self.get_header_string(http::header::ACCEPT_LANGUAGE)),
+            *crate::env::REALM_LANG,
+        )
+    }
+
+    pub(crate) fn set_lang(&self, lang: realm_lang::Language) {
+        self.lang.replace(Some(lang));
+    }
+
     pub(crate) fn set_step(&self, step: crate::rr::Step) {
         self.step.replace(Some(step));
     }
@@ -101,6 +140,8 @@ impl Context {
             step: std::cell::RefCell::new(None),
             is_test: false,
             meta: std::cell::RefCell::new(Default::default()),
+            nonce: crate::security::new_nonce(),
+            lang: std::cell::RefCell::new(None),
         }
     }
 
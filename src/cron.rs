@@ -0,0 +1,180 @@
+// Cron expressions with the usual five fields, in UTC:
+//
+//     minute hour day-of-month month day-of-week
+//
+// Each field is `*`, a number, a range `a-b`, a step `*/n` or `a-b/n`, or a
+// comma separated list of those. Day of week is 0 to 6, sunday is 0 (7 is
+// also sunday). As in cron, if both day of month and day of week are
+// restricted a day matching either one matches.
+
+use chrono::{DateTime, Datelike, Duration, TimeZone, Timelike, Utc};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Schedule {
+    minutes: Vec<bool>,
+    hours: Vec<bool>,
+    days: Vec<bool>,
+    months: Vec<bool>,
+    weekdays: Vec<bool>,
+    any_day: bool,
+    any_weekday: bool,
+}
+
+fn field(s: &str, min: u32, max: u32) -> Result<(Vec<bool>, bool), failure::Error> {
+    let mut set = vec![false; max as usize + 1];
+    for part in s.split(',') {
+        let (range, step) = match part.find('/') {
+            Some(i) => (&part[..i], part[i + 1..].parse::<u32>()?),
+            None => (part, 1),
+        };
+        if step == 0 {
+            return Err(format_err!("step can not be 0: {}", part));
+        }
+        let (from, to) = if range == "*" {
+            (min, max)
+        } else {
+            match range.find('-') {
+                Some(i) => (range[..i].parse()?, range[i + 1..].parse()?),
+                None if step > 1 => (range.parse()?, max),
+                None => {
+                    let v = range.parse()?;
+                    (v, v)
+                }
+            }
+        };
+        if from < min || to > max || from > to {
+            return Err(format_err!("{} is out of range {}-{}", part, min, max));
+        }
+        let mut v = from;
+        while v <= to {
+            set[v as usize] = true;
+            v += step;
+        }
+    }
+    Ok((set, s == "*"))
+}
+
+impl std::str::FromStr for Schedule {
+    type Err = failure::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<&str> = s.split_whitespace().collect();
+        if parts.len() != 5 {
+            return Err(format_err!("cron expression must have 5 fields: {}", s));
+        }
+
+        let (minutes, _) = field(parts[0], 0, 59)?;
+        let (hours, _) = field(parts[1], 0, 23)?;
+        let (days, any_day) = field(parts[2], 1, 31)?;
+        let (months, _) = field(parts[3], 1, 12)?;
+        let (mut weekdays, any_weekday) = field(parts[4], 0, 7)?;
+        if weekdays[7] {
+            weekdays[0] = true;
+        }
+
+        Ok(Schedule {
+            minutes,
+            hours,
+            days,
+            months,
+            weekdays,
+            any_day,
+            any_weekday,
+        })
+    }
+}
+
+impl Schedule {
+    fn day_matches(&self, t: &DateTime<Utc>) -> bool {
+        let day = self.days[t.day() as usize];
+        let weekday = self.weekdays[t.weekday().num_days_from_sunday() as usize];
+        match (self.any_day, self.any_weekday) {
+            (true, true) => true,
+            (true, false) => weekday,
+            (false, true) => day,
+            (false, false) => day || weekday,
+        }
+    }
+
+    pub fn matches(&self, t: &DateTime<Utc>) -> bool {
+        self.months[t.month() as usize]
+            && self.day_matches(t)
+            && self.hours[t.hour() as usize]
+            && self.minutes[t.minute() as usize]
+    }
+
+    // first matching minute strictly after t, None if there is none in next 5
+    // years, eg for 30th february
+    pub fn next_after(&self, t: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let mut t = Utc
+            .ymd(t.year(), t.month(), t.day())
+            .and_hms(t.hour(), t.minute(), 0)
+            + Duration::minutes(1);
+        let end = t + Duration::days(5 * 366);
+
+        while t < end {
+            if !self.months[t.month() as usize] {
+                let (y, m) = if t.month() == 12 {
+                    (t.year() + 1, 1)
+                } else {
+                    (t.year(), t.month() + 1)
+                };
+                t = Utc.ymd(y, m, 1).and_hms(0, 0, 0);
+            } else if !self.day_matches(&t) {
+                t = Utc.ymd(t.year(), t.month(), t.day()).and_hms(0, 0, 0) + Duration::days(1);
+            } else if !self.hours[t.hour() as usize] {
+                t = Utc
+                    .ymd(t.year(), t.month(), t.day())
+                    .and_hms(t.hour(), 0, 0)
+                    + Duration::hours(1);
+            } else if !self.minutes[t.minute() as usize] {
+                t = t + Duration::minutes(1);
+            } else {
+                return Some(t);
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    fn next(cron: &str, after: &str) -> String {
+        let s: super::Schedule = cron.parse().unwrap();
+        let after = chrono::DateTime::parse_from_rfc3339(after)
+            .unwrap()
+            .with_timezone(&chrono::Utc);
+        s.next_after(after).unwrap().to_rfc3339()
+    }
+
+    #[test]
+    fn next_after() {
+        let t = "2021-02-13T04:05:06+00:00";
+        assert_eq!(next("* * * * *", t), "2021-02-13T04:06:00+00:00");
+        assert_eq!(next("0 * * * *", t), "2021-02-13T05:00:00+00:00");
+        assert_eq!(next("30 3 * * *", t), "2021-02-14T03:30:00+00:00");
+        assert_eq!(next("*/15 4 * * *", t), "2021-02-13T04:15:00+00:00");
+        assert_eq!(next("0 0 1 * *", t), "2021-03-01T00:00:00+00:00");
+        // 13th feb 2021 is a saturday
+        assert_eq!(next("0 9 * * 1-5", t), "2021-02-15T09:00:00+00:00");
+        assert_eq!(next("0 9 * * 7", t), "2021-02-14T09:00:00+00:00");
+        assert_eq!(next("0 0 29 2 *", t), "2024-02-29T00:00:00+00:00");
+        // either day of month or day of week
+        assert_eq!(next("0 0 20 * 0", t), "2021-02-14T00:00:00+00:00");
+    }
+
+    #[test]
+    fn invalid() {
+        assert!("* * * *".parse::<super::Schedule>().is_err());
+        assert!("60 * * * *".parse::<super::Schedule>().is_err());
+        assert!("*/0 * * * *".parse::<super::Schedule>().is_err());
+        assert!("5-2 * * * *".parse::<super::Schedule>().is_err());
+        assert!("0 0 30 2 *"
+            .parse::<super::Schedule>()
+            .unwrap()
+            .next_after(chrono::Utc.ymd(2021, 1, 1).and_hms(0, 0, 0))
+            .is_none());
+    }
+}
@@ -0,0 +1,51 @@
+// Assets are created at build time by `realm_macros::embed_assets!()`, and
+// registered at startup, before the first request:
+//
+//     static ASSETS: realm::embed::Assets = realm_macros::embed_assets!();
+//
+//     fn main() {
+//         realm::embed::register(&ASSETS);
+//         ...
+//     }
+//
+// Once registered ftd.js and everything in static/ is served from
+// the binary and the current working directory is not read at all.
+
+pub struct Asset {
+    pub path: &'static str,
+    pub content: &'static [u8],
+    pub hash: &'static str,
+}
+
+pub struct Assets {
+    pub ftd_js: Option<&'static str>,
+    pub files: &'static [Asset],
+}
+
+lazy_static! {
+    static ref EMBEDDED: antidote::RwLock<Option<&'static Assets>> = antidote::RwLock::new(None);
+}
+
+pub fn register(assets: &'static Assets) {
+    println!("realm::embed: {} static files embedded", assets.files.len());
+    *EMBEDDED.write() = Some(assets);
+}
+
+pub fn is_embedded() -> bool {
+    EMBEDDED.read().is_some()
+}
+
+pub fn ftd_js() -> Option<&'static str> {
+    EMBEDDED.read().and_then(|a| a.ftd_js)
+}
+
+pub fn files() -> &'static [Asset] {
+    EMBEDDED.read().map(|a| a.files).unwrap_or(&[])
+}
+
+// path is the url path of the file, eg /static/elm.js
+pub fn get(path: &str) -> Option<&'static Asset> {
+    EMBEDDED
+        .read()
+        .and_then(|a| a.files.iter().find(|f| f.path == path))
+}
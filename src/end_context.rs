@@ -1,5 +1,4 @@
 pub use crate::response::Response;
-use diesel::{ExpressionMethods, RunQueryDsl};
 
 pub fn end_context<UD, NF>(
     in_: &crate::base::In<UD>,
@@ -11,7 +10,10 @@ where
     NF: FnOnce(&crate::base::In<UD>, &str) -> crate::Result,
 {
     crate::base::pg::rollback_if_required(in_.conn);
-    use crate::schema::realm_activity;
+    // the transaction that created tasks is over, they can be seen by workers
+    if in_.task_created.replace(false) {
+        crate::worker::wake();
+    }
 
     let mut response = serde_json::Value::Null; // empty data;
     let mut final_url = crate::utils::path_and_query(&crate::cleanup_url(&in_.ctx.url));
@@ -29,6 +31,9 @@ where
                 }
                 Response::Page(p) => {
                     observer::observe_string("id", p.id.as_str());
+                    if let Some(ref activity) = p.activity {
+                        in_.merge_activity(activity);
+                    }
 
                     if let Some(ref url) = p.url {
                         if final_url != url.as_str() {
@@ -141,40 +146,38 @@ where
             .as_i64()
             .unwrap_or_else(|| -1) as i32;
 
-        diesel::insert_into(realm_activity::table)
-            .values((
-                realm_activity::url.eq(in_.ctx.url.to_string()),
-                realm_activity::method.eq(in_.ctx.method.to_string()),
-                realm_activity::okind.eq(activity.okind),
-                realm_activity::oid.eq(activity.oid),
-                realm_activity::ekind.eq(activity.ekind),
-                realm_activity::data.eq(activity.data),
-                realm_activity::trace.eq(trace),
-                realm_activity::hash.eq(hash),
-                realm_activity::rust_trace.eq(rust_trace),
-                realm_activity::response.eq(response),
-                realm_activity::uid.eq(in_.user_id()),
-                realm_activity::when.eq(in_.now),
-                realm_activity::outcome.eq(outcome),
-                realm_activity::code.eq(code),
-                realm_activity::duration.eq(duration),
-                realm_activity::site_version.eq(site_version),
-                realm_activity::ua.eq(in_.user_agent().unwrap_or_else(|| "".to_string())),
-                realm_activity::ip.eq(ip),
-                realm_activity::sid.eq(in_.session_id()),
-                // tid, vic
-                realm_activity::tid.eq(tid),
-                realm_activity::tid_created.eq(tid_created),
-                realm_activity::vid.eq(vid),
-                realm_activity::vid_created.eq(vid_created),
-                // UTM
-                realm_activity::utm_source.eq(in_.ctx.query.get("utm_source")),
-                realm_activity::utm_medium.eq(in_.ctx.query.get("utm_medium")),
-                realm_activity::utm_campaign.eq(in_.ctx.query.get("utm_campaign")),
-                realm_activity::utm_term.eq(in_.ctx.query.get("utm_term")),
-                realm_activity::utm_content.eq(in_.ctx.query.get("utm_content")),
-            ))
-            .execute(in_.conn)?;
+        let mut record = crate::activity_sink::Record {
+            url: in_.ctx.url.to_string(),
+            method: in_.ctx.method.to_string(),
+            ua: in_.user_agent().unwrap_or_else(|| "".to_string()),
+            ip,
+            okind: activity.okind,
+            oid: activity.oid,
+            ekind: activity.ekind,
+            data: activity.data,
+            uid: in_.user_id(),
+            sid: in_.session_id(),
+            vid,
+            vid_created,
+            tid,
+            tid_created,
+            when: in_.now,
+            duration,
+            response,
+            outcome,
+            code,
+            trace,
+            hash,
+            rust_trace,
+            utm_source: in_.ctx.query.get("utm_source").cloned(),
+            utm_medium: in_.ctx.query.get("utm_medium").cloned(),
+            utm_campaign: in_.ctx.query.get("utm_campaign").cloned(),
+            utm_term: in_.ctx.query.get("utm_term").cloned(),
+            utm_content: in_.ctx.query.get("utm_content").cloned(),
+            site_version,
+        };
+        crate::activity_policy::policy().apply(&mut record);
+        crate::activity_sink::store(record);
     }
 
     match resp {
@@ -197,37 +200,7 @@ pub fn store_activity<UD>(in_: &crate::base::In<UD>) -> bool
 where
     UD: crate::UserData,
 {
-    let url = in_.ctx.url.path().to_lowercase();
-
-    if in_.ctx.is_crawler {
-        return false;
-    }
-
-    if url.ends_with(".php") {
-        return false;
-    }
-
-    if url.ends_with(".png") {
-        return false;
-    }
-
-    if url.starts_with("/static/") {
-        return false;
-    }
-
-    if url == "/favicon.ico" {
-        return false;
-    }
-
-    if url.starts_with("/test/") {
-        return false;
-    }
-
-    if url == "/iframe/" {
-        return false;
-    }
-
-    true
+    crate::activity_policy::policy().should_store(in_.ctx.url.path(), in_.ctx.is_crawler)
 }
 
 lazy_static! {
@@ -8,6 +8,49 @@ pub fn ctrl_c() -> Result<bool, failure::Error> {
     Ok(CONTROL_C.load(std::sync::atomic::Ordering::Relaxed))
 }
 
+// There is one ctrl-c handler for the process, shared by check() and
+// on_ctrl_c(). First ctrl-c runs the registered hooks, eg RealmService::http()
+// stops accepting requests, or is ignored if REALM_CATCH_CONTROL_C is set and
+// no hook is registered. A second ctrl-c exits right away.
+static CONTROL_C_COUNT: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+static CONTROL_C_HANDLER: std::sync::Once = std::sync::Once::new();
+
+lazy_static! {
+    static ref CONTROL_C_HOOKS: antidote::Mutex<Vec<Box<dyn FnOnce() + Send>>> =
+        antidote::Mutex::new(vec![]);
+}
+
+fn handle_ctrl_c() {
+    CONTROL_C_HANDLER.call_once(|| {
+        let r = ctrlc::set_handler(|| {
+            if CONTROL_C_COUNT.fetch_add(1, std::sync::atomic::Ordering::SeqCst) > 0 {
+                std::process::exit(130);
+            }
+
+            let hooks: Vec<_> = CONTROL_C_HOOKS.lock().drain(..).collect();
+            if !hooks.is_empty() {
+                println!("stopping, press CTRL-C again to exit now");
+                hooks.into_iter().for_each(|hook| hook());
+            } else if *REALM_CATCH_CONTROL_C {
+                println!("ignoring CTRL-C");
+                CONTROL_C.store(false, std::sync::atomic::Ordering::SeqCst);
+                CONTROL_C_COUNT.store(0, std::sync::atomic::Ordering::SeqCst);
+            } else {
+                std::process::exit(130);
+            }
+        });
+        if let Err(e) = r {
+            eprintln!("failed to set ctrl-c handler: {}", e);
+        }
+    });
+}
+
+// f is called on first ctrl-c
+pub fn on_ctrl_c<F: FnOnce() + Send + 'static>(f: F) {
+    CONTROL_C_HOOKS.lock().push(Box::new(f));
+    handle_ctrl_c();
+}
+
 pub fn bool_with_default(name: &str, default: bool) -> bool {
     match std::env::var(name) {
         Ok(v) => match v.trim().to_lowercase().as_str() {
@@ -55,8 +98,24 @@ lazy_static! {
         .unwrap_or_else(|_| "40".to_string())
         .parse()
         .unwrap();
+    pub static ref REALM_COMPRESS_MIN_SIZE: usize = std::env::var("REALM_COMPRESS_MIN_SIZE")
+        .unwrap_or_else(|_| "1024".to_string())
+        .parse()
+        .unwrap();
     pub static ref REALM_SECRET: String =
         std::env::var("REALM_SECRET").expect("REALM_SECRET not found");
+    pub static ref REALM_ACTIVITY_BATCH_SIZE: usize = std::env::var("REALM_ACTIVITY_BATCH_SIZE")
+        .unwrap_or_else(|_| "100".to_string())
+        .parse()
+        .unwrap();
+    pub static ref REALM_ACTIVITY_FLUSH_MS: u64 = std::env::var("REALM_ACTIVITY_FLUSH_MS")
+        .unwrap_or_else(|_| "1000".to_string())
+        .parse()
+        .unwrap();
+    pub static ref REALM_ACTIVITY_QUEUE_SIZE: usize = std::env::var("REALM_ACTIVITY_QUEUE_SIZE")
+        .unwrap_or_else(|_| "10000".to_string())
+        .parse()
+        .unwrap();
 }
 
 fn default_language() -> realm_lang::Language {
@@ -67,17 +126,30 @@ fn default_language() -> realm_lang::Language {
     }
 }
 
+// configuration that is read from env and can be wrong, checked at startup by
+// RealmService::http() and worker::run(), so a mistake fails the deploy and not
+// the first request that needs it
+pub fn init() -> Result<(), failure::Error> {
+    crate::serve_static::init()?;
+    crate::assets::init()?;
+    crate::activity_policy::init()?;
+    crate::activity_sink::init()?;
+    crate::activity_retention::init()?;
+    crate::worker::init()?;
+    Ok(())
+}
+
 pub fn check() {
     lazy_static::initialize(&REALM_LANG);
     lazy_static::initialize(&REALM_SITE_URL);
     lazy_static::initialize(&REALM_THREAD_POOL_SIZE);
+    lazy_static::initialize(&REALM_COMPRESS_MIN_SIZE);
     lazy_static::initialize(&REALM_SECRET);
+    lazy_static::initialize(&REALM_ACTIVITY_BATCH_SIZE);
+    lazy_static::initialize(&REALM_ACTIVITY_FLUSH_MS);
+    lazy_static::initialize(&REALM_ACTIVITY_QUEUE_SIZE);
 
     if *REALM_CATCH_CONTROL_C {
-        ctrlc::set_handler(|| {
-            println!("ignoring CTRL-C");
-            CONTROL_C.store(false, std::sync::atomic::Ordering::SeqCst);
-        })
-        .expect("Error setting Ctrl-C handler");
+        handle_ctrl_c();
     }
 }
@@ -0,0 +1,453 @@
+// Dev only pages to look at stored activity without writing sql:
+//
+// - /test/activity/: recent requests, filtered by path, outcome, code, uid,
+//   tid or shape hash
+// - /test/activity/detail/?id=<id>: one request, with its observer span tree
+//   and the sql queries it made
+// - /test/activity/shapes/: requests of last REALM_EXPLORER_DAYS (default 7)
+//   days grouped by observer::shape_hash(), a route with a new shape is doing
+//   something different from before
+//
+// They read realm_activity in postgres, so they only show something when the
+// postgres activity sink is used. Outside dev mode they are not found.
+
+use diesel::prelude::*;
+
+const LIMIT: i64 = 100;
+
+lazy_static! {
+    static ref DAYS: i32 = std::env::var("REALM_EXPLORER_DAYS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(7);
+}
+
+#[derive(Debug, Default)]
+pub struct Filter {
+    pub path: Option<String>,
+    pub outcome: Option<String>,
+    pub code: Option<String>,
+    pub uid: Option<String>,
+    pub tid: Option<String>,
+    pub hash: Option<String>,
+}
+
+impl Filter {
+    // empty form fields are not filters
+    pub fn new(input: &mut crate::request_config::RequestConfig) -> Result<Self, crate::Error> {
+        let mut get = |name: &str| -> Result<Option<String>, crate::Error> {
+            Ok(input
+                .optional::<String>(name)?
+                .filter(|v| !v.trim().is_empty()))
+        };
+
+        Ok(Filter {
+            path: get("path")?,
+            outcome: get("outcome")?,
+            code: get("code")?,
+            uid: get("uid")?,
+            tid: get("tid")?,
+            hash: get("hash")?,
+        })
+    }
+}
+
+#[derive(Queryable)]
+pub struct Row {
+    pub id: i32,
+    pub when: chrono::DateTime<chrono::Utc>,
+    pub method: String,
+    pub url: String,
+    pub outcome: String,
+    pub code: String,
+    pub duration: i32,
+    pub uid: Option<String>,
+    pub tid: String,
+    pub hash: String,
+}
+
+impl Row {
+    fn human_duration(&self) -> String {
+        human(i64::from(self.duration))
+    }
+
+    fn short_hash(&self) -> &str {
+        short(self.hash.as_str())
+    }
+}
+
+#[derive(QueryableByName)]
+pub struct Shape {
+    #[sql_type = "diesel::sql_types::Text"]
+    pub hash: String,
+    #[sql_type = "diesel::sql_types::BigInt"]
+    pub count: i64,
+    #[sql_type = "diesel::sql_types::BigInt"]
+    pub errors: i64,
+    #[sql_type = "diesel::sql_types::Timestamptz"]
+    pub first_seen: chrono::DateTime<chrono::Utc>,
+    #[sql_type = "diesel::sql_types::Timestamptz"]
+    pub last_seen: chrono::DateTime<chrono::Utc>,
+    #[sql_type = "diesel::sql_types::Integer"]
+    pub last_id: i32,
+    #[sql_type = "diesel::sql_types::Text"]
+    pub url: String,
+}
+
+impl Shape {
+    fn short_hash(&self) -> &str {
+        short(self.hash.as_str())
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Line {
+    pub indent: usize,
+    pub duration: String,
+    pub kind: &'static str,
+    pub text: String,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Query {
+    pub duration: String,
+    pub query: String,
+    pub bind: String,
+    pub result: String,
+}
+
+#[derive(askama::Template)]
+#[template(path = "explorer_list.html")]
+struct List<'a> {
+    filter: &'a Filter,
+    rows: Vec<Row>,
+}
+
+#[derive(askama::Template)]
+#[template(path = "explorer_detail.html")]
+struct Detail {
+    id: i32,
+    r: crate::activity_sink::Record,
+    spans: Vec<Line>,
+    queries: Vec<Query>,
+    data: String,
+    response: String,
+}
+
+#[derive(askama::Template)]
+#[template(path = "explorer_shapes.html")]
+struct Shapes {
+    path: String,
+    days: i32,
+    shapes: Vec<Shape>,
+}
+
+fn short(hash: &str) -> &str {
+    if hash.len() > 8 {
+        &hash[..8]
+    } else {
+        hash
+    }
+}
+
+// observer durations are in nanoseconds, same as frontend/Realm/Trace.elm
+pub fn human(d: i64) -> String {
+    if d < 0 {
+        "-".to_string()
+    } else if d < 1_000 {
+        format!("{}ns", d)
+    } else if d < 1_000_000 {
+        format!("{}µs", d / 1_000)
+    } else if d < 1_000_000_000 {
+        format!("{}ms", d / 1_000_000)
+    } else {
+        format!(
+            "{}s{}ms",
+            d / 1_000_000_000,
+            (d % 1_000_000_000) / 1_000_000
+        )
+    }
+}
+
+fn value_text(v: &serde_json::Value) -> String {
+    match v {
+        serde_json::Value::String(s) => s.clone(),
+        v => v.to_string(),
+    }
+}
+
+// span tree of observer trace, flattened in display order
+pub fn flatten(
+    span: &serde_json::Value,
+    depth: usize,
+    lines: &mut Vec<Line>,
+    queries: &mut Vec<Query>,
+) {
+    lines.push(Line {
+        indent: depth * 16,
+        duration: human(span["duration"].as_i64().unwrap_or(-1)),
+        kind: "span",
+        text: value_text(&span["id"]),
+    });
+
+    let items = match span["items"].as_array() {
+        Some(items) => items,
+        None => return,
+    };
+    for item in items.iter() {
+        let (duration, item) = (human(item[0].as_i64().unwrap_or(-1)), &item[1]);
+        let indent = (depth + 1) * 16;
+        match item["type"].as_str() {
+            Some("Frame") => flatten(item, depth + 1, lines, queries),
+            Some("Log") => lines.push(Line {
+                indent,
+                duration,
+                kind: "log",
+                text: value_text(&item["message"]),
+            }),
+            Some("Field") | Some("TransientField") => lines.push(Line {
+                indent,
+                duration,
+                kind: "field",
+                text: format!("{} = {}", value_text(&item["name"]), item["value"]),
+            }),
+            Some("Query") => {
+                let result = match (item["result"].get("Ok"), item["result"].get("Err")) {
+                    (Some(rows), _) => format!("{} rows", rows),
+                    (_, Some(e)) => format!("error: {}", value_text(e)),
+                    _ => "".to_string(),
+                };
+                lines.push(Line {
+                    indent,
+                    duration: duration.clone(),
+                    kind: "query",
+                    text: value_text(&item["query"]),
+                });
+                queries.push(Query {
+                    duration,
+                    query: value_text(&item["query"]),
+                    bind: item["bind"].as_str().unwrap_or("").to_string(),
+                    result,
+                });
+            }
+            _ => lines.push(Line {
+                indent,
+                duration,
+                kind: "item",
+                text: item.to_string(),
+            }),
+        }
+    }
+}
+
+fn not_found() -> crate::Error {
+    crate::Error::PageNotFound {
+        message: "activity explorer is only available in dev mode".to_string(),
+    }
+}
+
+fn html<T: askama::Template>(
+    ctx: &crate::Context,
+    t: &T,
+) -> Result<crate::Response, failure::Error> {
+    ctx.header(http::header::CONTENT_TYPE, "text/html; charset=utf-8");
+    Ok(crate::Response::Http(
+        ctx.response(t.render()?.into_bytes())?,
+    ))
+}
+
+pub fn list<UD>(
+    in_: &crate::base::In<UD>,
+    filter: &Filter,
+) -> Result<crate::Response, failure::Error>
+where
+    UD: crate::UserData,
+{
+    use crate::schema::realm_activity as a;
+
+    if !in_.is_dev() {
+        return Err(not_found().into());
+    }
+
+    let mut q = a::table
+        .select((
+            a::id,
+            a::when,
+            a::method,
+            a::url,
+            a::outcome,
+            a::code,
+            a::duration,
+            a::uid,
+            a::tid,
+            a::hash,
+        ))
+        .order(a::id.desc())
+        .limit(LIMIT)
+        .into_boxed();
+    if let Some(ref path) = filter.path {
+        q = q.filter(a::url.ilike(format!("%{}%", path)));
+    }
+    if let Some(ref outcome) = filter.outcome {
+        q = q.filter(a::outcome.eq(outcome));
+    }
+    if let Some(ref code) = filter.code {
+        q = q.filter(a::code.eq(code));
+    }
+    if let Some(ref uid) = filter.uid {
+        q = q.filter(a::uid.eq(uid));
+    }
+    if let Some(ref tid) = filter.tid {
+        q = q.filter(a::tid.eq(tid));
+    }
+    if let Some(ref hash) = filter.hash {
+        q = q.filter(a::hash.eq(hash));
+    }
+
+    let rows = q.load(in_.conn)?;
+    html(in_.ctx, &List { filter, rows })
+}
+
+pub fn detail<UD>(in_: &crate::base::In<UD>, id: i32) -> Result<crate::Response, failure::Error>
+where
+    UD: crate::UserData,
+{
+    if !in_.is_dev() {
+        return Err(not_found().into());
+    }
+
+    let r: crate::activity_sink::Record =
+        match diesel::sql_query("SELECT * FROM realm_activity WHERE id = $1")
+            .bind::<diesel::sql_types::Integer, _>(id)
+            .get_result(in_.conn)
+            .optional()?
+        {
+            Some(r) => r,
+            None => {
+                return Err(crate::Error::PageNotFound {
+                    message: format!("no activity: {}", id),
+                }
+                .into())
+            }
+        };
+
+    let (mut spans, mut queries) = (vec![], vec![]);
+    if let Some(stack) = r.trace["span_stack"].as_array() {
+        for span in stack.iter() {
+            flatten(span, 0, &mut spans, &mut queries);
+        }
+    }
+
+    html(
+        in_.ctx,
+        &Detail {
+            id,
+            spans,
+            queries,
+            data: serde_json::to_string_pretty(&r.data)?,
+            response: serde_json::to_string_pretty(&r.response)?,
+            r,
+        },
+    )
+}
+
+pub fn shapes<UD>(
+    in_: &crate::base::In<UD>,
+    path: Option<String>,
+) -> Result<crate::Response, failure::Error>
+where
+    UD: crate::UserData,
+{
+    if !in_.is_dev() {
+        return Err(not_found().into());
+    }
+
+    let path = path.unwrap_or_else(|| "".to_string());
+    let shapes = diesel::sql_query(
+        "SELECT \
+         hash, \
+         count(*) AS count, \
+         count(*) FILTER (WHERE outcome = 'server_error') AS errors, \
+         min(\"when\") AS first_seen, \
+         max(\"when\") AS last_seen, \
+         max(id) AS last_id, \
+         (array_agg(url ORDER BY id DESC))[1] AS url \
+         FROM realm_activity \
+         WHERE \"when\" > now() - make_interval(days => $1) AND url ILIKE $2 \
+         GROUP BY hash \
+         ORDER BY last_seen DESC \
+         LIMIT $3",
+    )
+    .bind::<diesel::sql_types::Integer, _>(*DAYS)
+    .bind::<diesel::sql_types::Text, _>(format!("%{}%", path))
+    .bind::<diesel::sql_types::BigInt, _>(LIMIT)
+    .load(in_.conn)?;
+
+    html(
+        in_.ctx,
+        &Shapes {
+            path,
+            days: *DAYS,
+            shapes,
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn human() {
+        assert_eq!(super::human(-1), "-");
+        assert_eq!(super::human(999), "999ns");
+        assert_eq!(super::human(12_345), "12µs");
+        assert_eq!(super::human(12_345_678), "12ms");
+        assert_eq!(super::human(2_500_000_000), "2s500ms");
+    }
+
+    #[test]
+    fn flatten() {
+        let trace = json!({
+            "id": "realm__handle",
+            "duration": 3_000_000,
+            "items": [
+                [10, {"type": "Log", "message": "hello"}],
+                [20, {"type": "Field", "name": "id", "value": 5}],
+                [30, {
+                    "type": "Frame",
+                    "id": "db__get",
+                    "duration": 2_000_000,
+                    "items": [
+                        [40, {"type": "Query", "query": "SELECT 1", "bind": null, "result": {"Ok": 1}}]
+                    ]
+                }]
+            ]
+        });
+
+        let (mut lines, mut queries) = (vec![], vec![]);
+        super::flatten(&trace, 0, &mut lines, &mut queries);
+
+        let summary: Vec<(usize, &str, &str)> = lines
+            .iter()
+            .map(|l| (l.indent, l.kind, l.text.as_str()))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (0, "span", "realm__handle"),
+                (16, "log", "hello"),
+                (16, "field", "id = 5"),
+                (16, "span", "db__get"),
+                (32, "query", "SELECT 1"),
+            ]
+        );
+        assert_eq!(
+            queries,
+            vec![super::Query {
+                duration: "40ns".to_string(),
+                query: "SELECT 1".to_string(),
+                bind: "".to_string(),
+                result: "1 rows".to_string(),
+            }]
+        );
+    }
+}
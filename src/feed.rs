@@ -0,0 +1,270 @@
+// Atom and RSS 2.0 feeds of articles, each item is summarised by its TLDR:
+//
+//     let feed = realm::feed::Feed {
+//         title: "Blog".to_string(),
+//         link: "https://example.com/blog/".to_string(),
+//         url: "https://example.com/blog/feed.xml".to_string(),
+//         ..Default::default()
+//     };
+//     feed.response(in_.ctx, realm::feed::Format::Atom)
+//
+// and pages advertise the feed with:
+//
+//     in_.ctx.meta().add_feed(feed.link(realm::feed::Format::Atom));
+
+use crate::utils::escape_xml;
+use chrono::{DateTime, Utc};
+
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Atom,
+    RSS,
+}
+
+impl Format {
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Format::Atom => "application/atom+xml",
+            Format::RSS => "application/rss+xml",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Item {
+    pub title: String,
+    pub link: String,
+    pub tldr: crate::TLDR,
+    pub author: Option<String>,
+    pub published: DateTime<Utc>,
+    pub updated: Option<DateTime<Utc>>,
+}
+
+impl Item {
+    // rendered tldr, with the image on top
+    fn summary(&self) -> String {
+        match self.tldr.image {
+            Some(ref image) => format!(
+                "<p><img src=\"{}\"></p>{}",
+                escape_xml(image.as_str()),
+                self.tldr.body.rendered
+            ),
+            None => self.tldr.body.rendered.clone(),
+        }
+    }
+
+    fn updated(&self) -> DateTime<Utc> {
+        self.updated.unwrap_or(self.published)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Feed {
+    pub title: String,
+    // url of the html page of the feed, eg the blog index
+    pub link: String,
+    // url of the feed itself
+    pub url: String,
+    pub description: Option<String>,
+    // used for items without author, atom requires one of them
+    pub author: Option<String>,
+    pub items: Vec<Item>,
+}
+
+impl Feed {
+    fn updated(&self) -> DateTime<Utc> {
+        self.items
+            .iter()
+            .map(Item::updated)
+            .max()
+            .unwrap_or_else(Utc::now)
+    }
+
+    pub fn link(&self, format: Format) -> crate::FeedLink {
+        match format {
+            Format::Atom => crate::FeedLink::atom(self.url.as_str(), Some(self.title.as_str())),
+            Format::RSS => crate::FeedLink::rss(self.url.as_str(), Some(self.title.as_str())),
+        }
+    }
+
+    pub fn render(&self, format: Format) -> String {
+        match format {
+            Format::Atom => self.atom(),
+            Format::RSS => self.rss(),
+        }
+    }
+
+    pub fn response(&self, ctx: &crate::Context, format: Format) -> crate::Result {
+        ctx.header(
+            http::header::CONTENT_TYPE,
+            format!("{}; charset=utf-8", format.content_type()).as_str(),
+        );
+        Ok(crate::Response::Http(
+            ctx.response(self.render(format).into_bytes())?,
+        ))
+    }
+
+    pub fn atom(&self) -> String {
+        let mut xml = format!(
+            "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n\
+             <feed xmlns=\"http://www.w3.org/2005/Atom\">\n  \
+             <title>{title}</title>\n  \
+             <id>{url}</id>\n  \
+             <link rel=\"self\" href=\"{url}\"/>\n  \
+             <link rel=\"alternate\" type=\"text/html\" href=\"{link}\"/>\n  \
+             <updated>{updated}</updated>\n",
+            title = escape_xml(self.title.as_str()),
+            url = escape_xml(self.url.as_str()),
+            link = escape_xml(self.link.as_str()),
+            updated = self.updated().to_rfc3339(),
+        );
+        if let Some(ref description) = self.description {
+            xml.push_str(&format!(
+                "  <subtitle>{}</subtitle>\n",
+                escape_xml(description.as_str())
+            ));
+        }
+        if let Some(ref author) = self.author {
+            xml.push_str(&format!(
+                "  <author><name>{}</name></author>\n",
+                escape_xml(author.as_str())
+            ));
+        }
+
+        for item in self.items.iter() {
+            xml.push_str(&format!(
+                "  <entry>\n    \
+                 <title>{title}</title>\n    \
+                 <id>{link}</id>\n    \
+                 <link rel=\"alternate\" type=\"text/html\" href=\"{link}\"/>\n    \
+                 <published>{published}</published>\n    \
+                 <updated>{updated}</updated>\n",
+                title = escape_xml(item.title.as_str()),
+                link = escape_xml(item.link.as_str()),
+                published = item.published.to_rfc3339(),
+                updated = item.updated().to_rfc3339(),
+            ));
+            if let Some(ref author) = item.author {
+                xml.push_str(&format!(
+                    "    <author><name>{}</name></author>\n",
+                    escape_xml(author.as_str())
+                ));
+            }
+            xml.push_str(&format!(
+                "    <summary type=\"html\">{}</summary>\n  </entry>\n",
+                escape_xml(item.summary().as_str())
+            ));
+        }
+
+        xml.push_str("</feed>\n");
+        xml
+    }
+
+    pub fn rss(&self) -> String {
+        let mut xml = format!(
+            "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n\
+             <rss version=\"2.0\" xmlns:atom=\"http://www.w3.org/2005/Atom\" \
+             xmlns:dc=\"http://purl.org/dc/elements/1.1/\">\n  \
+             <channel>\n    \
+             <title>{title}</title>\n    \
+             <link>{link}</link>\n    \
+             <description>{description}</description>\n    \
+             <atom:link href=\"{url}\" rel=\"self\" type=\"application/rss+xml\"/>\n    \
+             <lastBuildDate>{updated}</lastBuildDate>\n",
+            title = escape_xml(self.title.as_str()),
+            link = escape_xml(self.link.as_str()),
+            // description is required in rss
+            description = escape_xml(self.description.as_ref().unwrap_or(&self.title)),
+            url = escape_xml(self.url.as_str()),
+            updated = self.updated().to_rfc2822(),
+        );
+
+        for item in self.items.iter() {
+            xml.push_str(&format!(
+                "    <item>\n      \
+                 <title>{title}</title>\n      \
+                 <link>{link}</link>\n      \
+                 <guid isPermaLink=\"true\">{link}</guid>\n      \
+                 <pubDate>{published}</pubDate>\n",
+                title = escape_xml(item.title.as_str()),
+                link = escape_xml(item.link.as_str()),
+                published = item.published.to_rfc2822(),
+            ));
+            // rss <author> has to be an email address, dc:creator takes a name
+            if let Some(author) = item.author.as_ref().or_else(|| self.author.as_ref()) {
+                xml.push_str(&format!(
+                    "      <dc:creator>{}</dc:creator>\n",
+                    escape_xml(author.as_str())
+                ));
+            }
+            xml.push_str(&format!(
+                "      <description>{}</description>\n    </item>\n",
+                escape_xml(item.summary().as_str())
+            ));
+        }
+
+        xml.push_str("  </channel>\n</rss>\n");
+        xml
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    fn feed() -> super::Feed {
+        super::Feed {
+            title: "Blog & News".to_string(),
+            link: "https://example.com/blog/".to_string(),
+            url: "https://example.com/blog/feed.xml".to_string(),
+            description: None,
+            author: Some("Amit".to_string()),
+            items: vec![super::Item {
+                title: "Hello <World>".to_string(),
+                link: "https://example.com/blog/hello/".to_string(),
+                tldr: crate::TLDR {
+                    id: None,
+                    image: Some("https://example.com/hello.png".to_string()),
+                    body: ftd_rt::Rendered {
+                        original: "hello *world*".to_string(),
+                        rendered: "<p>hello <em>world</em></p>".to_string(),
+                    },
+                },
+                author: None,
+                published: chrono::Utc.ymd(2021, 2, 13).and_hms(4, 5, 6),
+                updated: None,
+            }],
+        }
+    }
+
+    #[test]
+    fn atom() {
+        let xml = feed().atom();
+        assert!(xml.contains("<title>Blog &amp; News</title>"));
+        assert!(xml.contains("<updated>2021-02-13T04:05:06+00:00</updated>"));
+        assert!(xml.contains("<author><name>Amit</name></author>"));
+        assert!(xml.contains("<title>Hello &lt;World&gt;</title>"));
+        assert!(xml.contains(
+            "<summary type=\"html\">&lt;p&gt;&lt;img src=&quot;https://example.com/hello.png\
+             &quot;&gt;&lt;/p&gt;&lt;p&gt;hello &lt;em&gt;world&lt;/em&gt;&lt;/p&gt;</summary>"
+        ));
+    }
+
+    #[test]
+    fn rss() {
+        let xml = feed().rss();
+        assert!(xml.contains("<description>Blog &amp; News</description>"));
+        assert!(xml.contains("<pubDate>Sat, 13 Feb 2021 04:05:06 +0000</pubDate>"));
+        assert!(xml.contains("<dc:creator>Amit</dc:creator>"));
+        assert!(xml.contains("<guid isPermaLink=\"true\">https://example.com/blog/hello/</guid>"));
+    }
+
+    #[test]
+    fn link() {
+        assert_eq!(
+            feed().link(super::Format::RSS),
+            crate::FeedLink::rss("https://example.com/blog/feed.xml", Some("Blog & News"))
+        );
+    }
+}
@@ -20,6 +20,112 @@ pub struct HTMLMeta {
     pub twitter_title: Option<String>,
     pub twitter_description: Option<String>,
     pub twitter_image: Option<String>,
+
+    pub canonical: Option<String>,
+    // <link rel="alternate" hreflang="..">, for the same page in other languages
+    #[serde(default)]
+    pub alternates: Vec<Alternate>,
+    // <link rel="alternate" type="application/rss+xml">
+    #[serde(default)]
+    pub feeds: Vec<FeedLink>,
+    pub prev: Option<String>,
+    pub next: Option<String>,
+    // extra robots directives, eg nofollow, noarchive, max-snippet:50, noindex
+    // is controlled by no_index
+    #[serde(default)]
+    pub robots: Vec<String>,
+
+    // schema.org structured data, at most one of each kind
+    #[serde(default)]
+    pub json_ld: Vec<crate::json_ld::StructuredData>,
+}
+
+#[derive(PartialEq, Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Alternate {
+    // language code, eg en, en-US or x-default
+    pub hreflang: String,
+    pub href: String,
+}
+
+#[derive(PartialEq, Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct FeedLink {
+    pub href: String,
+    pub title: Option<String>,
+    // application/rss+xml or application/atom+xml
+    pub content_type: String,
+}
+
+impl FeedLink {
+    pub fn rss(href: &str, title: Option<&str>) -> Self {
+        FeedLink {
+            href: href.to_string(),
+            title: title.map(ToString::to_string),
+            content_type: "application/rss+xml".to_string(),
+        }
+    }
+
+    pub fn atom(href: &str, title: Option<&str>) -> Self {
+        FeedLink {
+            content_type: "application/atom+xml".to_string(),
+            ..FeedLink::rss(href, title)
+        }
+    }
+}
+
+const P1_NAME: &str = "html-meta";
+const P1_KEYS: &[&str] = &[
+    "id",
+    "no-index",
+    "title",
+    "description",
+    "keywords",
+    "author",
+    "canonical",
+    "prev",
+    "next",
+    "robots",
+];
+
+#[derive(Fail, Debug)]
+pub enum HTMLMetaError {
+    #[fail(display = "expected {} section, found: {}", P1_NAME, name)]
+    InvalidSection { name: String },
+    #[fail(display = "unknown key in {}: {}", section, key)]
+    UnknownKey { section: String, key: String },
+    #[fail(display = "unknown sub-section: {}", name)]
+    UnknownSubSection { name: String },
+    #[fail(display = "invalid value for {}: {}", key, message)]
+    InvalidValue { key: String, message: String },
+}
+
+fn invalid(key: &str, e: impl std::fmt::Display) -> HTMLMetaError {
+    HTMLMetaError::InvalidValue {
+        key: key.to_string(),
+        message: e.to_string(),
+    }
+}
+
+fn optional(header: &ftd::p1::Header, key: &str) -> Result<Option<String>, HTMLMetaError> {
+    header.string_optional(key).map_err(|e| invalid(key, e))
+}
+
+fn required(header: &ftd::p1::Header, key: &str) -> Result<String, HTMLMetaError> {
+    header.string(key).map_err(|e| invalid(key, e))
+}
+
+// a typo in key name would otherwise be silently ignored
+fn check_keys(
+    section: &str,
+    header: &ftd::p1::Header,
+    known: &[&str],
+) -> Result<(), HTMLMetaError> {
+    match header.0.iter().find(|(k, _)| !known.contains(&k.as_str())) {
+        Some((k, _)) => Err(HTMLMetaError::UnknownKey {
+            section: section.to_string(),
+            key: k.to_string(),
+        }),
+        None => Ok(()),
+    }
 }
 
 impl HTMLMeta {
@@ -48,6 +154,13 @@ impl HTMLMeta {
             }
         }
 
+        if other.keywords.is_some() {
+            self.keywords = other.keywords.clone();
+        }
+        if other.author.is_some() {
+            self.author = other.author.clone();
+        }
+
         if other.og_type.is_some() {
             self.og_type = other.og_type.clone();
         }
@@ -88,6 +201,28 @@ impl HTMLMeta {
         if other.twitter_image.is_some() {
             self.twitter_image = other.twitter_image.clone();
         }
+
+        if other.canonical.is_some() {
+            self.canonical = other.canonical.clone();
+        }
+        for alternate in other.alternates.iter() {
+            self.add_alternate(alternate.hreflang.as_str(), alternate.href.as_str());
+        }
+        for feed in other.feeds.iter() {
+            self.add_feed(feed.clone());
+        }
+        if other.prev.is_some() {
+            self.prev = other.prev.clone();
+        }
+        if other.next.is_some() {
+            self.next = other.next.clone();
+        }
+        for directive in other.robots.iter() {
+            self.add_robots(directive.as_str());
+        }
+        for data in other.json_ld.iter() {
+            self.add_json_ld(data.clone());
+        }
     }
 
     pub fn merge_tldr(&mut self, tldr: &crate::TLDR) {
@@ -108,12 +243,31 @@ impl HTMLMeta {
                 self.twitter_image = tldr.image.clone();
             }
         }
+
+        for data in self.json_ld.iter_mut() {
+            if let crate::json_ld::StructuredData::Article(ref mut a) = data {
+                if a.description.is_none() {
+                    a.description = Some(tldr.body.original.clone());
+                }
+                if a.image.is_empty() {
+                    a.image.extend(tldr.image.clone());
+                }
+            }
+        }
     }
 
     pub fn to_p1(&self) -> ftd::p1::Section {
         let mut p1 = ftd::p1::Section::with_name("html-meta")
             .add_optional_header("id", &self.id)
-            .add_header_if_not_equal("no-index", "true", "false")
+            .add_header_if_not_equal(
+                "no-index",
+                if self.no_index.unwrap_or_default() {
+                    "true"
+                } else {
+                    "false"
+                },
+                "false",
+            )
             .add_optional_header("title", &self.title)
             .add_optional_header("description", &self.description)
             .add_optional_header("keywords", &self.keywords)
@@ -151,45 +305,126 @@ impl HTMLMeta {
             );
         }
 
+        p1 = p1
+            .add_optional_header("canonical", &self.canonical)
+            .add_optional_header("prev", &self.prev)
+            .add_optional_header("next", &self.next);
+        if !self.robots.is_empty() {
+            p1.header.add("robots", self.robots.join(", ").as_str());
+        }
+
+        for alternate in self.alternates.iter() {
+            let mut sub = ftd::p1::SubSection {
+                name: "alternate".to_string(),
+                ..Default::default()
+            };
+            sub.header.add("hreflang", alternate.hreflang.as_str());
+            sub.header.add("href", alternate.href.as_str());
+            p1.sub_sections.add(sub);
+        }
+
+        for feed in self.feeds.iter() {
+            let mut sub = ftd::p1::SubSection {
+                name: "feed".to_string(),
+                ..Default::default()
+            };
+            sub.header.add("href", feed.href.as_str());
+            sub.header.add("type", feed.content_type.as_str());
+            if let Some(ref title) = feed.title {
+                sub.header.add("title", title.as_str());
+            }
+            p1.sub_sections.add(sub);
+        }
+
         p1
     }
 
-    // pub fn from_p1(p1: &ftd::p1::Section) -> Result<Self, crate::document::ParseError> {
-    //     let mut o = Self {
-    //         id: p1.header.string_optional("id")?,
-    //         no_index: p1.header.bool_with_default("no-index", false)?,
-    //         title: p1.header.string_optional("title")?,
-    //         keywords: p1.header.string_optional("keywords")?,
-    //         author: p1.header.string_optional("author")?,
-    //
-    //         description: p1.body.clone(),
-    //         ..Default::default()
-    //     };
-    //
-    //     for sub in p1.sub_sections.0.iter() {
-    //         match sub.name.as_str() {
-    //             "og" | "open-graph" => {
-    //                 o.og_type = sub.header.string_optional("type")?;
-    //                 o.og_url = sub.header.string_optional("url")?;
-    //                 o.og_title = sub.header.string_optional("title")?;
-    //                 o.og_image = sub.header.string_optional("image")?;
-    //
-    //                 o.og_description = sub.body.clone();
-    //             }
-    //             "twitter" => {
-    //                 o.twitter_card = sub.header.string_optional("card")?;
-    //                 o.twitter_url = sub.header.string_optional("url")?;
-    //                 o.twitter_title = sub.header.string_optional("title")?;
-    //                 o.twitter_image = sub.header.string_optional("image")?;
-    //
-    //                 o.twitter_description = sub.body.clone();
-    //             }
-    //             t => return crate::document::err(format!("unknown sub-section: {}", t).as_str())?,
-    //         }
-    //     }
-    //
-    //     Ok(o)
-    // }
+    pub fn from_p1(p1: &ftd::p1::Section) -> Result<Self, HTMLMetaError> {
+        if p1.name != P1_NAME {
+            return Err(HTMLMetaError::InvalidSection {
+                name: p1.name.clone(),
+            });
+        }
+        check_keys(P1_NAME, &p1.header, P1_KEYS)?;
+
+        let mut o = Self {
+            id: optional(&p1.header, "id")?,
+            no_index: if p1
+                .header
+                .bool_with_default("no-index", false)
+                .map_err(|e| invalid("no-index", e))?
+            {
+                Some(true)
+            } else {
+                None
+            },
+            title: optional(&p1.header, "title")?,
+            // to_p1() writes description as header, hand written content has
+            // it in body
+            description: optional(&p1.header, "description")?.or_else(|| p1.body.clone()),
+            keywords: optional(&p1.header, "keywords")?,
+            author: optional(&p1.header, "author")?,
+            canonical: optional(&p1.header, "canonical")?,
+            prev: optional(&p1.header, "prev")?,
+            next: optional(&p1.header, "next")?,
+            ..Default::default()
+        };
+        if let Some(robots) = optional(&p1.header, "robots")? {
+            for directive in robots.split(',') {
+                o.add_robots(directive);
+            }
+        }
+
+        for sub in p1.sub_sections.0.iter() {
+            match sub.name.as_str() {
+                "og" | "open-graph" => {
+                    check_keys(
+                        sub.name.as_str(),
+                        &sub.header,
+                        &["type", "url", "title", "image"],
+                    )?;
+                    o.og_type = optional(&sub.header, "type")?;
+                    o.og_url = optional(&sub.header, "url")?;
+                    o.og_title = optional(&sub.header, "title")?;
+                    o.og_image = optional(&sub.header, "image")?;
+
+                    o.og_description = sub.body.clone();
+                }
+                "twitter" => {
+                    check_keys("twitter", &sub.header, &["card", "url", "title", "image"])?;
+                    o.twitter_card = optional(&sub.header, "card")?;
+                    o.twitter_url = optional(&sub.header, "url")?;
+                    o.twitter_title = optional(&sub.header, "title")?;
+                    o.twitter_image = optional(&sub.header, "image")?;
+
+                    o.twitter_description = sub.body.clone();
+                }
+                "alternate" => {
+                    check_keys("alternate", &sub.header, &["hreflang", "href"])?;
+                    o.add_alternate(
+                        required(&sub.header, "hreflang")?.as_str(),
+                        required(&sub.header, "href")?.as_str(),
+                    );
+                }
+                "feed" => {
+                    check_keys("feed", &sub.header, &["href", "type", "title"])?;
+                    o.add_feed(FeedLink {
+                        href: required(&sub.header, "href")?,
+                        title: optional(&sub.header, "title")?,
+                        content_type: optional(&sub.header, "type")?
+                            .unwrap_or_else(|| "application/rss+xml".to_string()),
+                    });
+                }
+                t => {
+                    return Err(HTMLMetaError::UnknownSubSection {
+                        name: t.to_string(),
+                    })
+                }
+            }
+        }
+
+        Ok(o)
+    }
 
     pub fn set_title(&mut self, title: &str) {
         self.title = Some(title.to_string());
@@ -256,6 +491,138 @@ impl HTMLMeta {
         self.twitter_image = Some(v.to_string())
     }
 
+    pub fn canonical(&mut self, v: &str) {
+        self.canonical = Some(v.to_string())
+    }
+
+    // adds or replaces the link for given language
+    pub fn add_alternate(&mut self, hreflang: &str, href: &str) {
+        match self.alternates.iter_mut().find(|a| a.hreflang == hreflang) {
+            Some(a) => a.href = href.to_string(),
+            None => self.alternates.push(Alternate {
+                hreflang: hreflang.to_string(),
+                href: href.to_string(),
+            }),
+        }
+    }
+
+    pub fn add_feed(&mut self, feed: FeedLink) {
+        match self.feeds.iter_mut().find(|f| f.href == feed.href) {
+            Some(f) => *f = feed,
+            None => self.feeds.push(feed),
+        }
+    }
+
+    pub fn prev(&mut self, v: &str) {
+        self.prev = Some(v.to_string())
+    }
+
+    pub fn next(&mut self, v: &str) {
+        self.next = Some(v.to_string())
+    }
+
+    pub fn add_robots(&mut self, directive: &str) {
+        let directive = directive.trim();
+        if directive == "noindex" {
+            self.no_index = Some(true);
+        } else if !directive.is_empty() && !self.robots.iter().any(|r| r == directive) {
+            self.robots.push(directive.to_string());
+        }
+    }
+
+    // replaces the existing structured data of the same kind
+    pub fn add_json_ld(&mut self, data: crate::json_ld::StructuredData) {
+        match self
+            .json_ld
+            .iter_mut()
+            .find(|d| std::mem::discriminant(&**d) == std::mem::discriminant(&data))
+        {
+            Some(d) => *d = data,
+            None => self.json_ld.push(data),
+        }
+    }
+
+    // canonical defaults to the url of the page on REALM_SITE_URL, without the
+    // realm_* query parameters
+    pub fn default_canonical(&mut self, path_and_query: &str) {
+        if self.canonical.is_some() {
+            return;
+        }
+
+        let mut url = match url::Url::parse(crate::env::site_url().as_str())
+            .and_then(|base| base.join(path_and_query))
+        {
+            Ok(url) => url,
+            Err(_) => return,
+        };
+
+        let query: Vec<(String, String)> = url
+            .query_pairs()
+            .into_owned()
+            .filter(|(k, _)| !k.starts_with("realm_"))
+            .collect();
+        if query.is_empty() {
+            url.set_query(None);
+        } else {
+            url.query_pairs_mut().clear().extend_pairs(query);
+        }
+        url.set_fragment(None);
+
+        self.canonical = Some(url.to_string());
+    }
+
+    fn links_html(&self) -> String {
+        fn link(rel: &str, href: &str, extra: &str) -> String {
+            format!(
+                "<link rel=\"{}\" href=\"{}\"{}>",
+                rel,
+                escape_attr(href),
+                extra
+            )
+        }
+
+        let mut links = vec![];
+        if let Some(ref canonical) = self.canonical {
+            links.push(link("canonical", canonical, ""));
+        }
+        for alternate in self.alternates.iter() {
+            links.push(link(
+                "alternate",
+                alternate.href.as_str(),
+                format!(" hreflang=\"{}\"", escape_attr(alternate.hreflang.as_str())).as_str(),
+            ));
+        }
+        for feed in self.feeds.iter() {
+            let mut extra = format!(" type=\"{}\"", escape_attr(feed.content_type.as_str()));
+            if let Some(ref title) = feed.title {
+                extra.push_str(&format!(" title=\"{}\"", escape_attr(title.as_str())));
+            }
+            links.push(link("alternate", feed.href.as_str(), extra.as_str()));
+        }
+        if let Some(ref prev) = self.prev {
+            links.push(link("prev", prev, ""));
+        }
+        if let Some(ref next) = self.next {
+            links.push(link("next", next, ""));
+        }
+
+        links.join("\n    ")
+    }
+
+    fn robots_content(&self) -> Option<String> {
+        let mut directives = vec![];
+        if self.no_index.unwrap_or_default() {
+            directives.push("noindex");
+        }
+        directives.extend(self.robots.iter().map(String::as_str));
+
+        if directives.is_empty() {
+            None
+        } else {
+            Some(directives.join(", "))
+        }
+    }
+
     pub fn to_html(&self, title: &str) -> String {
         fn meta(
             key: &'static str,
@@ -265,14 +632,12 @@ impl HTMLMeta {
         ) -> String {
             // TODO: escape value
             match (value, default) {
-                (Some(ref v), _) => {
-                    format!(
-                        "<meta {}=\"{}\" content=\"{}\">",
-                        key,
-                        escape(name),
-                        escape(v.as_str()),
-                    )
-                }
+                (Some(ref v), _) => format!(
+                    "<meta {}=\"{}\" content=\"{}\">",
+                    key,
+                    escape(name),
+                    escape(v.as_str()),
+                ),
                 (None, Some(v)) => format!(
                     "<meta {}=\"{}\" content=\"{}\">",
                     key,
@@ -291,7 +656,6 @@ impl HTMLMeta {
             meta("property", name, value, default)
         }
 
-        let noindex = Some("noindex".to_string());
         format!(
             r#"
 
@@ -316,8 +680,12 @@ impl HTMLMeta {
     {twitter_title}
     {twitter_description}
     {twitter_image}
+
+    {links}
+
+    {json_ld}
         "#,
-            robots = name("robots", if self.no_index.unwrap_or_default() { &noindex} else { &None }, None),
+            robots = name("robots", &self.robots_content(), None),
             viewport = name("viewport", &None, Some("width=device-width, height=device-height, initial-scale=1.0, user-scalable=no, user-scalable=0,  viewport-fit=cover")),
             title = name("title", &self.title, Some(title)),
             description = name("description", &self.description, None),
@@ -335,6 +703,13 @@ impl HTMLMeta {
             twitter_title = property("twitter:title", &self.twitter_title, None),
             twitter_description = property("twitter:description", &self.twitter_description, None),
             twitter_image = property("twitter:image", &self.twitter_image, None),
+            links = self.links_html(),
+            json_ld = self
+                .json_ld
+                .iter()
+                .map(crate::json_ld::StructuredData::to_html)
+                .collect::<Vec<_>>()
+                .join("\n    "),
         )
     }
 }
@@ -346,55 +721,178 @@ pub fn escape(s: &str) -> String {
     s.replace('&', "\\u0026")
 }
 
+// html entities, for attribute values which are urls, escape() would break
+// the query string
+fn escape_attr(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
 #[cfg(test)]
 mod tests {
+    #[test]
+    fn merge_links() {
+        let mut m = super::HTMLMeta::default();
+        m.add_alternate("en", "https://example.com/en/");
+        m.add_robots("nofollow");
+
+        let mut other = super::HTMLMeta::default();
+        other.canonical("https://example.com/a/");
+        other.add_alternate("en", "https://example.com/en/a/");
+        other.add_alternate("hi", "https://example.com/hi/a/");
+        other.add_robots("nofollow");
+        other.add_robots("noindex");
+        other.next("https://example.com/a/?page=2");
+        m.merge(&other);
+
+        assert_eq!(m.canonical, Some("https://example.com/a/".to_string()));
+        assert_eq!(
+            m.alternates,
+            vec![
+                super::Alternate {
+                    hreflang: "en".to_string(),
+                    href: "https://example.com/en/a/".to_string()
+                },
+                super::Alternate {
+                    hreflang: "hi".to_string(),
+                    href: "https://example.com/hi/a/".to_string()
+                },
+            ]
+        );
+        assert_eq!(m.robots_content(), Some("noindex, nofollow".to_string()));
+
+        let html = m.to_html("title");
+        assert!(html.contains("<meta name=\"robots\" content=\"noindex, nofollow\">"));
+        assert!(html.contains("<link rel=\"canonical\" href=\"https://example.com/a/\">"));
+        assert!(html.contains(
+            "<link rel=\"alternate\" href=\"https://example.com/hi/a/\" hreflang=\"hi\">"
+        ));
+        assert!(html.contains("<link rel=\"next\" href=\"https://example.com/a/?page=2\">"));
+    }
+
+    #[test]
+    fn json_ld() {
+        let mut m = super::HTMLMeta::default();
+        m.add_json_ld(crate::json_ld::StructuredData::Article(
+            crate::json_ld::Article {
+                headline: "first".to_string(),
+                ..Default::default()
+            },
+        ));
+        m.add_json_ld(crate::json_ld::StructuredData::Article(
+            crate::json_ld::Article {
+                headline: "second".to_string(),
+                ..Default::default()
+            },
+        ));
+        assert_eq!(m.json_ld.len(), 1);
+        assert!(m
+            .to_html("title")
+            .contains("<script type=\"application/ld+json\">{\"@context\""));
+    }
+
+    #[test]
+    fn default_canonical() {
+        let mut m = super::HTMLMeta::default();
+        m.default_canonical("/a/?realm_mode=ssr&page=2#top");
+        assert_eq!(
+            m.canonical,
+            Some(format!(
+                "{}/a/?page=2",
+                crate::env::site_url().trim_end_matches('/')
+            ))
+        );
+
+        m.default_canonical("/b/");
+        assert_eq!(
+            m.canonical,
+            Some(format!(
+                "{}/a/?page=2",
+                crate::env::site_url().trim_end_matches('/')
+            ))
+        );
+    }
+
+    #[test]
+    fn from_p1() {
+        let p1 = ftd::p1::parse(
+            "-- html-meta:\n\
+             title: hello there\n\
+             canonical: https://example.com/hello/\n\
+             robots: nofollow, noindex\n\
+             \n\
+             this is the description\n\
+             \n\
+             --- twitter:\n\
+             title: twitter title\n\
+             \n\
+             twitter description\n\
+             \n\
+             --- alternate:\n\
+             hreflang: hi\n\
+             href: https://example.com/hi/hello/\n",
+        )
+        .unwrap();
+
+        let mut expected = super::HTMLMeta {
+            no_index: Some(true),
+            title: Some("hello there".to_string()),
+            description: Some("this is the description".to_string()),
+            twitter_title: Some("twitter title".to_string()),
+            twitter_description: Some("twitter description".to_string()),
+            canonical: Some("https://example.com/hello/".to_string()),
+            robots: vec!["nofollow".to_string()],
+            ..Default::default()
+        };
+        expected.add_alternate("hi", "https://example.com/hi/hello/");
+        assert_eq!(super::HTMLMeta::from_p1(&p1[0]).unwrap(), expected);
+    }
+
+    #[test]
+    fn from_p1_errors() {
+        let p1 = ftd::p1::parse("-- html-meta:\ntitel: hello\n").unwrap();
+        match super::HTMLMeta::from_p1(&p1[0]) {
+            Err(super::HTMLMetaError::UnknownKey { section, key }) => {
+                assert_eq!(section, "html-meta");
+                assert_eq!(key, "titel");
+            }
+            r => panic!("expected UnknownKey, got: {:?}", r),
+        }
 
-    // #[test]
-    // fn test() {
-    //     p(
-    //         &indoc::indoc!(
-    //             "
-    //              -- html-meta:
-    //              title: hello there
-
-    //              this is the description
-    //         "
-    //         ),
-    //         &vec![crate::Section::HTMLMeta(crate::HTMLMeta {
-    //             title: Some("hello there".to_string()),
-    //             description: Some("this is the description".to_string()),
-    //             ..Default::default()
-    //         })],
-    //     );
-
-    //     p(
-    //         &indoc::indoc!(
-    //             "
-    //              -- html-meta:
-    //              title: hello there
-
-    //              this is the description
-
-    //              --- twitter:
-    //              title: twitter title
-
-    //              twitter description
-
-    //              --- open-graph:
-    //              title: og title
-
-    //              og description
-    //         "
-    //         ),
-    //         &vec![crate::Section::HTMLMeta(crate::HTMLMeta {
-    //             title: Some("hello there".to_string()),
-    //             description: Some("this is the description".to_string()),
-    //             twitter_title: Some("twitter title".to_string()),
-    //             twitter_description: Some("twitter description".to_string()),
-    //             og_title: Some("og title".to_string()),
-    //             og_description: Some("og description".to_string()),
-    //             ..Default::default()
-    //         })],
-    //     );
-    // }
+        let p1 = ftd::p1::parse("-- html-meta:\n\n--- facebook:\ntitle: hello\n").unwrap();
+        match super::HTMLMeta::from_p1(&p1[0]) {
+            Err(super::HTMLMetaError::UnknownSubSection { name }) => assert_eq!(name, "facebook"),
+            r => panic!("expected UnknownSubSection, got: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn p1_round_trip() {
+        let mut m = super::HTMLMeta {
+            id: Some("hello".to_string()),
+            no_index: Some(true),
+            title: Some("hello there".to_string()),
+            description: Some("this is the description".to_string()),
+            keywords: Some("a, b".to_string()),
+            author: Some("amitu".to_string()),
+            og_type: Some("article".to_string()),
+            og_title: Some("og title".to_string()),
+            og_description: Some("og description".to_string()),
+            twitter_card: Some("summary".to_string()),
+            twitter_description: Some("twitter description".to_string()),
+            canonical: Some("https://example.com/hello/".to_string()),
+            prev: Some("https://example.com/hello/?page=1".to_string()),
+            robots: vec!["nofollow".to_string(), "noarchive".to_string()],
+            ..Default::default()
+        };
+        m.add_alternate("en", "https://example.com/en/hello/");
+        m.add_feed(super::FeedLink::atom(
+            "https://example.com/feed.xml",
+            Some("Blog"),
+        ));
+
+        assert_eq!(super::HTMLMeta::from_p1(&m.to_p1()).unwrap(), m);
+    }
 }
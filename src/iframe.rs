@@ -8,16 +8,22 @@ where
         });
     }
 
-    Ok(crate::Response::Http(
-        in_.ctx.response(
-            crate::page::HTML_PAGE
-                .replace("__realm_meta__", "")
-                .replace("__realm_body__", "")
-                .replace(
-                    &format!("{}/elm.", &crate::page::CURRENT.to_string()),
-                    "iframe.",
-                )
-                .into(),
-        )?,
-    ))
+    let lang = in_.ctx.lang();
+    let html = crate::shell::scripts("/static/iframe.js")
+        .and_then(|scripts| {
+            crate::shell::render(&crate::shell::Slots {
+                lang: lang.id(),
+                title: "",
+                head: "".to_string(),
+                data: None,
+                body: "",
+                scripts,
+                nonce: in_.ctx.nonce(),
+            })
+        })
+        .map_err(|e| crate::Error::CustomError {
+            message: e.to_string(),
+        })?;
+
+    Ok(crate::Response::Http(in_.ctx.response(html.into())?))
 }
@@ -0,0 +1,228 @@
+// schema.org structured data, rendered by HTMLMeta::to_html() as
+// <script type="application/ld+json">. Only the fields we use are modelled,
+// optional fields that are not set are left out of the output.
+
+use chrono::{DateTime, Utc};
+
+#[derive(PartialEq, Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub enum StructuredData {
+    Article(Article),
+    BreadcrumbList(Vec<Breadcrumb>),
+    Organization(Organization),
+    Product(Product),
+}
+
+#[derive(PartialEq, Debug, Clone, serde::Serialize, serde::Deserialize, Default)]
+pub struct Article {
+    pub headline: String,
+    pub description: Option<String>,
+    pub image: Vec<String>,
+    pub url: Option<String>,
+    // name of the person
+    pub author: Option<String>,
+    pub publisher: Option<Organization>,
+    pub date_published: Option<DateTime<Utc>>,
+    pub date_modified: Option<DateTime<Utc>>,
+}
+
+#[derive(PartialEq, Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Breadcrumb {
+    pub name: String,
+    pub url: String,
+}
+
+#[derive(PartialEq, Debug, Clone, serde::Serialize, serde::Deserialize, Default)]
+pub struct Organization {
+    pub name: String,
+    pub url: Option<String>,
+    pub logo: Option<String>,
+    // profile urls, eg twitter, github
+    pub same_as: Vec<String>,
+}
+
+#[derive(PartialEq, Debug, Clone, serde::Serialize, serde::Deserialize, Default)]
+pub struct Product {
+    pub name: String,
+    pub description: Option<String>,
+    pub image: Vec<String>,
+    pub sku: Option<String>,
+    pub brand: Option<String>,
+    pub offer: Option<Offer>,
+}
+
+#[derive(PartialEq, Debug, Clone, serde::Serialize, serde::Deserialize, Default)]
+pub struct Offer {
+    pub price: String,
+    // ISO 4217, eg INR
+    pub currency: String,
+    // eg https://schema.org/InStock
+    pub availability: Option<String>,
+    pub url: Option<String>,
+}
+
+struct Object(serde_json::Map<String, serde_json::Value>);
+
+impl Object {
+    fn new(kind: &str) -> Self {
+        let mut m = serde_json::Map::new();
+        m.insert("@type".to_string(), json!(kind));
+        Object(m)
+    }
+
+    fn set<T: serde::Serialize>(mut self, key: &str, value: T) -> Self {
+        self.0.insert(key.to_string(), json!(value));
+        self
+    }
+
+    fn optional<T: serde::Serialize>(self, key: &str, value: &Option<T>) -> Self {
+        match value {
+            Some(v) => self.set(key, v),
+            None => self,
+        }
+    }
+
+    fn list(self, key: &str, value: &[String]) -> Self {
+        if value.is_empty() {
+            self
+        } else {
+            self.set(key, value)
+        }
+    }
+
+    fn object(self, key: &str, value: Option<Object>) -> Self {
+        match value {
+            Some(v) => self.set(key, v.0),
+            None => self,
+        }
+    }
+}
+
+impl Organization {
+    fn object(&self) -> Object {
+        Object::new("Organization")
+            .set("name", &self.name)
+            .optional("url", &self.url)
+            .optional("logo", &self.logo)
+            .list("sameAs", &self.same_as)
+    }
+}
+
+impl StructuredData {
+    pub fn to_json(&self) -> serde_json::Value {
+        let o = match self {
+            StructuredData::Article(a) => Object::new("Article")
+                .set("headline", &a.headline)
+                .optional("description", &a.description)
+                .list("image", &a.image)
+                .optional("url", &a.url)
+                .object(
+                    "author",
+                    a.author
+                        .as_ref()
+                        .map(|name| Object::new("Person").set("name", name)),
+                )
+                .object("publisher", a.publisher.as_ref().map(Organization::object))
+                .optional("datePublished", &a.date_published)
+                .optional("dateModified", &a.date_modified),
+            StructuredData::BreadcrumbList(items) => Object::new("BreadcrumbList").set(
+                "itemListElement",
+                items
+                    .iter()
+                    .enumerate()
+                    .map(|(i, b)| {
+                        Object::new("ListItem")
+                            .set("position", i + 1)
+                            .set("name", &b.name)
+                            .set("item", &b.url)
+                            .0
+                    })
+                    .collect::<Vec<_>>(),
+            ),
+            StructuredData::Organization(o) => o.object(),
+            StructuredData::Product(p) => Object::new("Product")
+                .set("name", &p.name)
+                .optional("description", &p.description)
+                .list("image", &p.image)
+                .optional("sku", &p.sku)
+                .object(
+                    "brand",
+                    p.brand
+                        .as_ref()
+                        .map(|name| Object::new("Brand").set("name", name)),
+                )
+                .object(
+                    "offers",
+                    p.offer.as_ref().map(|o| {
+                        Object::new("Offer")
+                            .set("price", &o.price)
+                            .set("priceCurrency", &o.currency)
+                            .optional("availability", &o.availability)
+                            .optional("url", &o.url)
+                    }),
+                ),
+        };
+
+        o.set("@context", "https://schema.org").0.into()
+    }
+
+    // the json is escaped so it can not close the script tag
+    pub fn to_html(&self) -> String {
+        format!(
+            "<script type=\"application/ld+json\">{}</script>",
+            crate::page::escape(self.to_json().to_string().as_str())
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn to_html() {
+        let article = super::StructuredData::Article(super::Article {
+            headline: "</script><script>alert(1)</script>".to_string(),
+            author: Some("Amit".to_string()),
+            ..Default::default()
+        });
+
+        assert_eq!(
+            article.to_json(),
+            json!({
+                "@context": "https://schema.org",
+                "@type": "Article",
+                "headline": "</script><script>alert(1)</script>",
+                "author": {"@type": "Person", "name": "Amit"},
+            })
+        );
+
+        let html = article.to_html();
+        assert!(html.starts_with("<script type=\"application/ld+json\">"));
+        assert!(!html.contains("</script><script>"));
+        assert!(html.contains("\\u003C/script\\u003E"));
+    }
+
+    #[test]
+    fn breadcrumbs() {
+        let b = super::StructuredData::BreadcrumbList(vec![
+            super::Breadcrumb {
+                name: "Home".to_string(),
+                url: "https://example.com/".to_string(),
+            },
+            super::Breadcrumb {
+                name: "Blog".to_string(),
+                url: "https://example.com/blog/".to_string(),
+            },
+        ]);
+
+        assert_eq!(
+            b.to_json(),
+            json!({
+                "@context": "https://schema.org",
+                "@type": "BreadcrumbList",
+                "itemListElement": [
+                    {"@type": "ListItem", "position": 1, "name": "Home", "item": "https://example.com/"},
+                    {"@type": "ListItem", "position": 2, "name": "Blog", "item": "https://example.com/blog/"},
+                ]
+            })
+        );
+    }
+}
@@ -31,19 +31,34 @@ extern crate askama;
 ))]
 compile_error!("only one of postgre_default, mysql_default or sqlite_default can be activated");
 
+pub mod activity;
+pub mod activity_policy;
+pub mod activity_retention;
+pub mod activity_sink;
+pub mod analytics;
+pub mod assets;
 pub mod base;
 mod context;
+pub mod cron;
+pub mod embed;
 mod end_context;
 pub mod env;
+pub mod explorer;
+pub mod feed;
 mod html_meta;
 pub mod iframe;
+pub mod json_ld;
 mod mode;
 mod page;
 pub mod request_config;
 mod response;
 pub mod schema;
+pub mod security;
 pub mod serve_static;
+pub mod shell;
+pub mod sitemap;
 pub mod storybook;
+pub mod stream;
 pub mod test;
 mod tldr;
 mod urls;
@@ -55,9 +70,10 @@ pub mod rr;
 pub mod serve;
 pub mod worker;
 
+pub use crate::activity::Activity;
 pub use crate::context::{cookies_from_request, Context};
 pub use crate::end_context::end_context;
-pub use crate::html_meta::HTMLMeta;
+pub use crate::html_meta::{Alternate, FeedLink, HTMLMeta, HTMLMetaError};
 pub use crate::mode::Mode;
 pub use crate::page::{Page, PageSpec};
 pub use crate::request_config::RequestConfig;
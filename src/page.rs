@@ -15,14 +15,7 @@ impl Default for CacheSpec {
     }
 }
 
-#[derive(Debug)]
-pub struct Activity {
-    pub okind: String,
-    pub oid: String,
-    pub ekind: String,
-    pub success: bool,
-    pub data: serde_json::Value,
-}
+pub use crate::activity::Activity;
 
 #[derive(serde::Serialize, Debug)]
 pub struct PageSpec {
@@ -60,53 +53,45 @@ impl PageSpec {
     }
 
     pub fn json_with_template(&self) -> Result<serde_json::Value, failure::Error> {
-        // not yet sure this function (attaching template to every outgoing json) is a
-        // good idea, maybe index.html should be part of js builds?
-        let html = get_page();
-        let data = serde_json::to_value(&self)?;
-
-        let data = match data {
-            serde_json::Value::Object(m) => {
-                let _h2 = html
-                    .replace("__realm_hash__", CURRENT.as_str())
-                    .replace("__realm_body__", "");
-                // m.insert("template".to_string(), serde_json::Value::String(h2));
-                serde_json::Value::Object(m)
-            }
-            _ => data,
-        };
-
-        Ok(data)
+        Ok(serde_json::to_value(&self)?)
     }
 
     pub fn render(
         &self,
+        ctx: &crate::Context,
         is_crawler: bool,
-        meta: std::cell::RefMut<crate::HTMLMeta>,
     ) -> Result<Vec<u8>, failure::Error> {
-        let data = escape(serde_json::to_string_pretty(&self.json_with_template()?)?.as_str());
-        let title = escape(&self.title);
-        let mut html = get_page();
-        html = html
-            .replace("__realm_title__", title.as_str())
-            .replace("__realm_meta__", meta.to_html(title.as_str()).as_str())
-            .replace(
-                "__ftd_js__",
-                std::fs::read_to_string("ftd/ftd.js")?.as_str(),
-            );
-
-        if is_crawler {
-            html = html
-                .replace("__realm_body__", &self.rendered)
-                .replace("__realm_data__", "")
-                .replace("<script src='/static/__hash__/elm.js'></script>", "")
-                .replace("type='module'", "type='ignore-please'");
-        } else {
-            html = html
-                .replace("__realm_data__", &data)
-                .replace("__realm_body__", "")
-                .replace("__realm_hash__", CURRENT.as_str());
+        let lang = ctx.lang();
+        let mut meta = ctx.meta();
+        // Page::with() does not know the url, it is filled by the time page is
+        // rendered
+        if let Some(ref url) = self.url {
+            meta.default_canonical(url.as_str());
         }
+        let (data, scripts) = if is_crawler {
+            (None, vec![])
+        } else {
+            (
+                Some(escape(
+                    serde_json::to_string_pretty(&self.json_with_template()?)?.as_str(),
+                )),
+                crate::shell::scripts(crate::shell::elm_url().as_str())?,
+            )
+        };
+
+        let html = crate::shell::render(&crate::shell::Slots {
+            lang: lang.id(),
+            title: self.title.as_str(),
+            head: meta.to_html(escape(&self.title).as_str()),
+            data,
+            body: if is_crawler {
+                self.rendered.as_str()
+            } else {
+                ""
+            },
+            scripts,
+            nonce: ctx.nonce(),
+        })?;
 
         Ok(html.into())
     }
@@ -207,90 +192,58 @@ pub trait Page: serde::ser::Serialize + askama::Template {
     ) -> Result<crate::Response, failure::Error> {
         self.with(
             title,
-            Some(Activity {
-                okind: okind.to_string(),
-                oid: oid.to_string(),
-                ekind: ekind.to_string(),
-                data: edata,
-                success,
+            Some({
+                let mut a = Activity::new(okind, oid, ekind);
+                a.merge(&Activity {
+                    data: edata,
+                    ..Default::default()
+                });
+                // only failures are recorded, success is the default
+                if !success {
+                    a.add_data("success", serde_json::Value::Bool(false));
+                }
+                a
             }),
             CacheSpec::default(),
         )
     }
 }
 
-pub(crate) fn get_page() -> String {
+pub(crate) fn get_ftd_js() -> Result<String, failure::Error> {
+    if let Some(ftd_js) = crate::embed::ftd_js() {
+        return Ok(ftd_js.to_string());
+    }
+
     if cfg!(debug_assertions) {
-        read_index()
+        read_ftd_js().map_err(Into::into)
     } else {
-        HTML_PAGE.clone()
+        match FTD_JS.as_ref() {
+            Ok(t) => Ok(t.clone()),
+            Err(e) => Err(failure::err_msg(format!("{}", e))),
+        }
     }
 }
 
-fn read_index() -> String {
-    let proj_dir = std::env::current_dir().expect("Could not find current dir");
-    let path =
-        proj_dir.join(std::env::var("REALM_INDEX").unwrap_or_else(|_| "index.html".to_string()));
-    match std::fs::read_to_string(path) {
-        Ok(p) => p,
-        Err(_err) => default_page(),
-    }
+fn read_ftd_js() -> std::io::Result<String> {
+    std::fs::read_to_string("ftd/ftd.js")
 }
 
 pub(crate) fn read_current() -> String {
+    if let Some(current) = crate::embed::get("/static/current.txt") {
+        return String::from_utf8_lossy(current.content).to_string();
+    }
+
     let proj_dir = std::env::current_dir().expect("Could not find current dir");
     let path = proj_dir.join(
         std::env::var("REALM_CURRENT_HASH_FILE")
             .unwrap_or_else(|_| "static/current.txt".to_string()),
     );
-    std::fs::read_to_string(path).expect("current.txt missing")
+    // current.txt is written by the elm build, if it is not there the version
+    // of asset manifest is used
+    std::fs::read_to_string(path).unwrap_or_else(|_| crate::assets::get_manifest().version.clone())
 }
 
 lazy_static! {
-    pub static ref HTML_PAGE: String = read_index();
     pub static ref CURRENT: String = read_current();
-}
-
-pub fn default_page() -> String {
-    r#"<!DOCTYPE html>
-<html>
-    <head>
-        <meta charset="utf-8" />
-        <title>__realm_title__</title>
-        <meta name="viewport" content="width=device-width" />
-        <script id="data" type="application/json">
-__realm_data__
-        </script>
-        <style>p {margin: 0}</style>
-    </head>
-    <body>
-    __realm_body__
-    <div id="main"></div>
-    <script src="/static/__hash__/elm.js"></script>
-
-    <script type="module">
-      import init, { create } from '/static/__hash__/ftd_rt.js';
-
-      async function run() {
-        await init();
-
-        function inner() {
-          if (!window.realm) {
-              window.requestAnimationFrame(inner);
-              return;
-          }
-
-          realm(create);
-        }
-
-        window.requestAnimationFrame(inner);
-      }
-
-      run();
-    </script>
-
-  </body>
-</html>
-"#
-    .to_string()
+    pub static ref FTD_JS: std::io::Result<String> = read_ftd_js();
 }
@@ -6,6 +6,7 @@ use crate::PageSpec;
 #[derive(serde::Serialize)]
 pub enum Response {
     Http(#[serde(serialize_with = "vec8")] http::response::Response<Vec<u8>>),
+    Stream(#[serde(serialize_with = "stream_len")] http::response::Response<crate::stream::Body>),
     JSON {
         data: Result<serde_json::Value, serde_json::Value>,
         context: Option<serde_json::Value>,
@@ -23,6 +24,91 @@ where
     ser.serialize_i32(resp.body().len() as i32)
 }
 
+fn stream_len<S>(
+    resp: &http::response::Response<crate::stream::Body>,
+    ser: S,
+) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    ser.serialize_i32(resp.body().len.map(|l| l as i32).unwrap_or(-1))
+}
+
+// pretty printing is only useful while developing, in production it just
+// makes the payload bigger
+fn to_json<T>(data: &T) -> serde_json::Result<Vec<u8>>
+where
+    T: serde::Serialize,
+{
+    if cfg!(debug_assertions) || crate::base::is_test() {
+        serde_json::to_vec_pretty(data)
+    } else {
+        serde_json::to_vec(data)
+    }
+}
+
+fn accepted_encoding(header: Option<&str>) -> Option<&'static str> {
+    let accepted: Vec<&str> = header
+        .unwrap_or("")
+        .split(',')
+        .filter_map(|v| {
+            let mut parts = v.split(';');
+            let name = parts.next()?.trim();
+            let rejected = parts.any(|p| {
+                let p = p.trim();
+                p.starts_with("q=") && p[2..].trim().parse::<f32>().map_or(false, |q| q <= 0.0)
+            });
+            if rejected {
+                None
+            } else {
+                Some(name)
+            }
+        })
+        .collect();
+
+    if accepted.contains(&"br") {
+        Some("br")
+    } else if accepted.contains(&"gzip") {
+        Some("gzip")
+    } else {
+        None
+    }
+}
+
+fn compress(ctx: &crate::Context, body: Vec<u8>) -> Result<Vec<u8>, failure::Error> {
+    use std::io::Write;
+
+    ctx.header(http::header::VARY, "Accept-Encoding");
+    if body.len() < *crate::env::REALM_COMPRESS_MIN_SIZE {
+        return Ok(body);
+    }
+
+    match accepted_encoding(
+        ctx.get_header_string(http::header::ACCEPT_ENCODING)
+            .as_deref(),
+    ) {
+        Some("br") => {
+            let mut out = Vec::new();
+            {
+                // quality 5 is a good trade off between size and cpu for
+                // responses we compress on every request
+                let mut writer = brotli::CompressorWriter::new(&mut out, 4096, 5, 22);
+                writer.write_all(body.as_slice())?;
+            }
+            ctx.header(http::header::CONTENT_ENCODING, "br");
+            Ok(out)
+        }
+        Some("gzip") => {
+            let mut encoder =
+                flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+            encoder.write_all(body.as_slice())?;
+            ctx.header(http::header::CONTENT_ENCODING, "gzip");
+            Ok(encoder.finish()?)
+        }
+        _ => Ok(body),
+    }
+}
+
 #[observed(with_result, namespace = "realm__response")]
 pub fn json<T>(data: &T) -> crate::Result
 where
@@ -125,12 +211,16 @@ impl Response {
             Response::Http(r) => {
                 return Ok(r);
             }
+            Response::Stream(r) => {
+                let (parts, body) = r.into_parts();
+                return Ok(http::Response::from_parts(parts, body.into_bytes()?));
+            }
             Response::JSON {
                 data,
                 context,
                 trace,
             } => {
-                return Ok(ctx.response(serde_json::to_vec_pretty(&match data {
+                let body = to_json(&match data {
                     Ok(data) => json!({
                         "success": true,
                         "result": data,
@@ -143,7 +233,8 @@ impl Response {
                         "context": context,
                         "trace": trace,
                     }),
-                })?)?)
+                })?;
+                return Ok(ctx.response(compress(ctx, body)?)?);
             }
         };
 
@@ -170,22 +261,38 @@ impl Response {
             );
         }
 
-        Ok(ctx.response(match ctx.mode {
-            Mode::API => serde_json::to_string_pretty(&spec.config)?.into(),
-            Mode::HTML => spec.render(false, ctx.meta())?,
-            Mode::SSR => spec.render(true, ctx.meta())?,
-            Mode::ISED | Mode::Pure => {
-                serde_json::to_string_pretty(&spec.json_with_template()?)?.into()
-            }
-            Mode::Submit => serde_json::to_string_pretty(&json!({
+        let body = match ctx.mode {
+            Mode::API => to_json(&spec.config)?,
+            Mode::HTML => spec.render(ctx, false)?,
+            Mode::SSR => spec.render(ctx, true)?,
+            Mode::ISED | Mode::Pure => to_json(&spec.json_with_template()?)?,
+            Mode::Submit => to_json(&json!({
                 "success": true,
                 "result": {
                     "kind": "navigate",
                     "data": spec.json_with_template()?,
                 }
-            }))?
-            .into(),
-        })?)
+            }))?,
+        };
+
+        Ok(ctx.response(compress(ctx, body)?)?)
+    }
+
+    pub fn into_hyper(
+        self,
+        ctx: &crate::Context,
+        url: &url::Url,
+    ) -> std::result::Result<hyper::Response<hyper::Body>, failure::Error> {
+        let mut resp = match self {
+            Response::Stream(r) => {
+                let (parts, body) = r.into_parts();
+                hyper::Response::from_parts(parts, body.into_hyper())
+            }
+            r => crate::serve::http_to_hyper(r.render(ctx, url)?),
+        };
+
+        crate::security::SECURITY_HEADERS.apply(ctx.nonce(), resp.headers_mut());
+        Ok(resp)
     }
 
     pub fn plain(ctx: &crate::Context, resp: String, status: http::StatusCode) -> crate::Result {
@@ -234,6 +341,24 @@ mod tests {
     use http::Response as HttpResponse;
     use serde_json::Value::Null;
 
+    #[test]
+    fn accepted_encoding() {
+        assert_eq!(super::accepted_encoding(None), None);
+        assert_eq!(super::accepted_encoding(Some("identity")), None);
+        assert_eq!(
+            super::accepted_encoding(Some("gzip, deflate")),
+            Some("gzip")
+        );
+        assert_eq!(
+            super::accepted_encoding(Some("gzip, deflate, br")),
+            Some("br")
+        );
+        assert_eq!(
+            super::accepted_encoding(Some("br;q=0, gzip;q=0.8")),
+            Some("gzip")
+        );
+    }
+
     #[test]
     fn test_http_resp_default() {
         let http_resp = HttpResponse::default();
@@ -1,3 +1,4 @@
+pub use crate::activity::Activity;
 use crate::base::*;
 
 pub const COOKIE_NAME: &str = "recording";
@@ -142,7 +143,7 @@ impl Step {
 
         s.body = serde_json::from_str(&p1.sub_sections.body_for(STEP_BODY)?)?;
         s.test_trace = p1.sub_sections.body_for(TRACE_NAME)?;
-        s.activity = Activity::from_p1(p1.sub_sections.by_name(ACTIVITY_NAME)?)?;
+        s.activity = Activity::from_p1(p1.sub_sections.by_name(crate::activity::P1_NAME)?)?;
 
         Ok(s)
     }
@@ -194,40 +195,6 @@ impl Step {
     }
 }
 
-const ACTIVITY_NAME: &str = "realm.rr.step.activity";
-
-#[derive(serde::Serialize, Default)]
-pub struct Activity {
-    pub okind: String,
-    pub oid: String,
-    pub ekind: String,
-    pub data: serde_json::Value,
-}
-
-impl Activity {
-    pub fn to_p1(&self) -> Result<ftd::p1::SubSection> {
-        let mut p1 = ftd::p1::SubSection {
-            name: ACTIVITY_NAME.to_string(),
-            body: Some(serde_json::to_string_pretty(&self.data)?),
-            ..Default::default()
-        };
-        p1.header.add("okind", self.okind.as_str());
-        p1.header.add("oid", self.oid.as_str());
-        p1.header.add("ekind", self.ekind.as_str());
-
-        Ok(p1)
-    }
-
-    pub fn from_p1(p1: &ftd::p1::SubSection) -> Result<Self> {
-        Ok(Activity {
-            okind: p1.header.string_with_default("okind", "")?,
-            oid: p1.header.string_with_default("oid", "")?,
-            ekind: p1.header.string_with_default("ekind", "")?,
-            data: serde_json::from_str(p1.body()?.as_str())?,
-        })
-    }
-}
-
 pub fn tid_to_path(tid: &str) -> std::path::PathBuf {
     std::path::PathBuf::from(format!("tests/{}.p1", tid))
 }
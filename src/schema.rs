@@ -32,6 +32,20 @@ table! {
     }
 }
 
+table! {
+    realm_activity_daily (id) {
+        id -> Int4,
+        day -> Date,
+        method -> Text,
+        route -> Text,
+        outcome -> Text,
+        code -> Text,
+        count -> Int4,
+        p50 -> Int4,
+        p95 -> Int4,
+    }
+}
+
 table! {
     realm_task (id) {
         id -> Int4,
@@ -44,5 +58,16 @@ table! {
         priority -> Int4,
         created_on -> Timestamptz,
         updated_on -> Timestamptz,
+        run_after -> Timestamptz,
+        last_error -> Nullable<Text>,
+        lease_until -> Nullable<Timestamptz>,
+        schedule -> Nullable<Text>,
+        scheduled_for -> Nullable<Timestamptz>,
+        key -> Nullable<Text>,
+        result -> Nullable<Jsonb>,
+        started_on -> Nullable<Timestamptz>,
+        finished_on -> Nullable<Timestamptz>,
+        duration -> Nullable<Integer>,
+        created_by -> Nullable<Text>,
     }
 }
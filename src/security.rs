@@ -0,0 +1,104 @@
+// Security headers are added to every response unless the handler has already
+// set them. Each one can be changed using its env variable, setting it to empty
+// string disables the header:
+//
+// - REALM_HSTS: Strict-Transport-Security, only sent by release builds
+// - REALM_CONTENT_TYPE_OPTIONS: X-Content-Type-Options
+// - REALM_REFERRER_POLICY: Referrer-Policy
+// - REALM_FRAME_OPTIONS: X-Frame-Options, only on html responses
+// - REALM_CSP: Content-Security-Policy, only on html responses, `{nonce}` is
+//   replaced with the per request nonce, eg "script-src 'self' 'nonce-{nonce}'"
+
+pub struct SecurityHeaders {
+    pub hsts: Option<String>,
+    pub content_type_options: Option<String>,
+    pub referrer_policy: Option<String>,
+    pub frame_options: Option<String>,
+    pub csp: Option<String>,
+}
+
+lazy_static! {
+    pub static ref SECURITY_HEADERS: SecurityHeaders = SecurityHeaders::from_env();
+}
+
+fn header_from_env(name: &str, default: Option<&str>) -> Option<String> {
+    match std::env::var(name) {
+        Ok(v) if v.trim().is_empty() => None,
+        Ok(v) => Some(v.trim().to_string()),
+        Err(_) => default.map(ToString::to_string),
+    }
+}
+
+impl SecurityHeaders {
+    pub fn from_env() -> Self {
+        SecurityHeaders {
+            hsts: header_from_env(
+                "REALM_HSTS",
+                if cfg!(debug_assertions) {
+                    None
+                } else {
+                    Some("max-age=31536000; includeSubDomains")
+                },
+            ),
+            content_type_options: header_from_env("REALM_CONTENT_TYPE_OPTIONS", Some("nosniff")),
+            referrer_policy: header_from_env(
+                "REALM_REFERRER_POLICY",
+                Some("strict-origin-when-cross-origin"),
+            ),
+            frame_options: header_from_env("REALM_FRAME_OPTIONS", Some("SAMEORIGIN")),
+            csp: header_from_env("REALM_CSP", None),
+        }
+    }
+
+    pub fn apply(&self, nonce: &str, headers: &mut http::HeaderMap<http::HeaderValue>) {
+        let is_html = headers
+            .get(http::header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.starts_with("text/html"))
+            .unwrap_or(false);
+
+        set_default(headers, http::header::STRICT_TRANSPORT_SECURITY, &self.hsts);
+        set_default(
+            headers,
+            http::header::X_CONTENT_TYPE_OPTIONS,
+            &self.content_type_options,
+        );
+        set_default(
+            headers,
+            http::header::REFERRER_POLICY,
+            &self.referrer_policy,
+        );
+
+        if is_html {
+            set_default(headers, http::header::X_FRAME_OPTIONS, &self.frame_options);
+            set_default(
+                headers,
+                http::header::CONTENT_SECURITY_POLICY,
+                &self.csp.as_ref().map(|csp| csp.replace("{nonce}", nonce)),
+            );
+        }
+    }
+}
+
+fn set_default(
+    headers: &mut http::HeaderMap<http::HeaderValue>,
+    name: http::header::HeaderName,
+    value: &Option<String>,
+) {
+    if headers.contains_key(&name) {
+        return;
+    }
+
+    if let Some(value) = value {
+        match http::HeaderValue::from_str(value.as_str()) {
+            Ok(v) => {
+                headers.insert(name, v);
+            }
+            Err(e) => eprintln!("invalid value for {}: {}", name, e),
+        }
+    }
+}
+
+pub fn new_nonce() -> String {
+    crate::utils::get_random_alphanumeric_string(22)
+}
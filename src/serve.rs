@@ -207,7 +207,7 @@ impl<T: Middleware + Sync + std::marker::Send + 'static> RealmService<T> {
             let url = crate::utils::to_url(uri.path_and_query().map(|p| p.as_str()).unwrap_or("/"));
             let url = crate::cleanup_url(&url);
 
-            let r = match res.and_then(|r| r.render(&ctx, &url)).map(http_to_hyper) {
+            let r = match res.and_then(|r| r.into_hyper(&ctx, &url)) {
                 Ok(a) => Ok(a),
                 Err(e) => {
                     println!("error: {:?}", e);
@@ -240,66 +240,9 @@ impl<T: Middleware + Sync + std::marker::Send + 'static> RealmService<T> {
         }
     }
 
-    fn handle_worker(&self, conn: &crate::base::pg::RealmConnection) -> crate::base::Result<usize> {
-        let tasks = crate::worker::latest(conn, 5)?;
-        let count = tasks.len();
-        if count > 0 {
-            println!("picked event from realm_task: {}", count);
-        }
-        for task in tasks.into_iter() {
-            let method = {
-                // TODO: use task.method
-                http::Method::POST
-            };
-            match self.middleware.handle(&crate::Context::from(
-                method,
-                task.path.as_str(),
-                task.data,
-                serde_json::from_value(task.cookies)?,
-            )) {
-                Ok(_t) => {
-                    crate::worker::updated_status(
-                        conn,
-                        task.id,
-                        task.number_tries,
-                        crate::worker::TaskStatus::Processed,
-                    )?;
-                    println!("task_processed: {}", task.id);
-                    // observer::observe_string(
-                    //     "task_processed",
-                    //     format!("{}", task.id).as_str(),
-                    // );
-                }
-                Err(e) => {
-                    crate::worker::updated_status(
-                        conn,
-                        task.id,
-                        task.number_tries,
-                        crate::worker::TaskStatus::Failed,
-                    )?;
-                    println!("task_process_error: {}", e);
-                    // observer::observe_string("process_err", format!("{}", e).as_str());
-                }
-            };
-        }
-        Ok(count)
-    }
-
-    pub fn worker(&self) {
-        let conn = crate::base::pg::connection();
-        println!("Starting realm::RealmService::worker");
-        while !crate::env::ctrl_c().expect("ctrl-c issue") {
-            let c = match self.handle_worker(&conn) {
-                Ok(c) => c,
-                Err(e) => {
-                    observer::observe_string("main_process_err", format!("{}", e).as_str());
-                    0
-                }
-            };
-            if c == 0 {
-                std::thread::sleep(std::time::Duration::from_secs(2));
-            }
-        }
+    // runs tasks with the middleware as handler, see realm::worker::run()
+    pub fn worker(self) {
+        crate::worker::run(move |ctx| self.middleware.handle(ctx))
     }
 
     pub fn http(self) {
@@ -320,7 +263,25 @@ impl<T: Middleware + Sync + std::marker::Send + 'static> RealmService<T> {
                     };
                 }
             }
+        } else if std::env::args().any(|e| e == "--build-assets") {
+            crate::assets::build_assets();
+        } else if std::env::args().any(|e| e == "--activity-maintenance") {
+            if let Err(e) = crate::env::init() {
+                eprintln!("invalid configuration: {}", e);
+                std::process::exit(1);
+            }
+            match crate::activity_retention::run(&crate::base::pg::connection()) {
+                Ok(r) => println!(
+                    "activity maintenance done, rolled up: {}, archived: {}, deleted: {}",
+                    r.rolled_up, r.archived, r.deleted
+                ),
+                Err(e) => println!("activity maintenance failed: {:?}", e),
+            }
         } else {
+            if let Err(e) = crate::env::init() {
+                eprintln!("invalid configuration: {}", e);
+                std::process::exit(1);
+            }
             let this = Arc::new(self);
 
             let port = std::env::var("PORT")
@@ -329,6 +290,13 @@ impl<T: Middleware + Sync + std::marker::Send + 'static> RealmService<T> {
                 .unwrap();
             let addr = ([0, 0, 0, 0], port).into();
 
+            // on ctrl-c stop accepting requests and write the queued activity,
+            // a second ctrl-c exits without waiting for open connections
+            let (stop, stopped) = futures::sync::oneshot::channel::<()>();
+            crate::env::on_ctrl_c(move || {
+                let _ = stop.send(());
+            });
+
             let server = hyper::Server::bind(&addr)
                 .serve(move || {
                     let this = this.clone();
@@ -345,10 +313,12 @@ impl<T: Middleware + Sync + std::marker::Send + 'static> RealmService<T> {
                         }))
                     })
                 })
+                .with_graceful_shutdown(stopped.map_err(|_| ()))
                 .map_err(|e| eprintln!("server error: {}", e));
 
             println!("Listening on http://{}", addr);
             hyper::rt::run(server);
+            crate::activity_sink::flush();
         }
     }
 }
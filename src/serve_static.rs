@@ -12,74 +12,55 @@ pub fn serve_static(ctx: &crate::Context) -> Result<crate::Response, failure::Er
         path
     };
 
-    let cache_control = if path.contains(".hashed-") {
-        "immutable, public, max-age=3600000000"
-    } else {
-        ""
-    };
+    let cache_control = cache_control(path);
 
-    observe_field("cache_control", cache_control);
+    observe_field("cache_control", cache_control.as_str());
 
-    if ctx
-        .get_header("Accept-Encoding")
-        .map(|v| format!("{:?}", v).contains("br"))
-        .unwrap_or(false)
-    {
-        if let Ok(content) = static_content((path.to_string() + ".br").as_str())
-            .or_else(|_| static_content(&format!("{}.br", path)))
-        {
-            println!(
-                "ok: br {:?} {} in {}",
-                &ctx.method,
-                path,
-                crate::base::elapsed(start)
-            );
-            return Ok(crate::Response::Http(
-                http::Response::builder()
-                    .header("Cache-Control", cache_control)
-                    .header("Content-Encoding", "br")
-                    .header("Content-Type", mime.to_string())
-                    .header("Service-Worker-Allowed", "/")
-                    .status(http::StatusCode::OK)
-                    .body(content)?,
-            ));
-        };
-    }
-    if ctx
+    let accept_encoding = ctx
         .get_header("Accept-Encoding")
-        .map(|v| format!("{:?}", v).contains("gzip"))
-        .unwrap_or(false)
-    {
-        if let Ok(content) = static_content((path.to_string() + ".gz").as_str())
-            .or_else(|_| static_content(&format!("{}.gz", path)))
-        {
-            println!(
-                "ok: gz {:?} {} in {}",
-                &ctx.method,
-                path,
-                crate::base::elapsed(start)
-            );
-            return Ok(crate::Response::Http(
-                http::Response::builder()
-                    .header("Cache-Control", cache_control)
-                    .header("Content-Encoding", "gzip")
-                    .header("Content-Type", mime.to_string())
-                    .header("Service-Worker-Allowed", "/")
-                    .status(http::StatusCode::OK)
-                    .body(content)?,
-            ));
+        .map(|v| format!("{:?}", v))
+        .unwrap_or_else(|| "".to_string());
+
+    // if a .br or .gz sibling exists the response depends on Accept-Encoding,
+    // even when the client gets the plain file
+    let mut vary = false;
+    for (encoding, extension) in [("br", "br"), ("gzip", "gz")].iter() {
+        let source = match static_source(&format!("{}.{}", path, extension)) {
+            Ok(source) => source,
+            Err(_) => continue,
         };
+        vary = true;
+
+        if !accept_encoding.contains(encoding) {
+            continue;
+        }
+
+        println!(
+            "ok: {} {:?} {} in {}",
+            extension,
+            &ctx.method,
+            path,
+            crate::base::elapsed(start)
+        );
+        return serve_source(
+            ctx,
+            source,
+            cache_control.as_str(),
+            mime.to_string().as_str(),
+            Some(*encoding),
+            true,
+        );
     }
 
-    match static_content(path) {
-        Ok(content) => Ok(crate::Response::Http(
-            http::Response::builder()
-                .header("Cache-Control", cache_control)
-                .header("Content-Type", mime.to_string())
-                .header("Service-Worker-Allowed", "/")
-                .status(http::StatusCode::OK)
-                .body(content)?,
-        )),
+    match static_source(path) {
+        Ok(source) => serve_source(
+            ctx,
+            source,
+            cache_control.as_str(),
+            mime.to_string().as_str(),
+            None,
+            vary,
+        ),
         Err(e) => {
             eprintln!("err: {} {}", e.to_string(), path);
             Ok(crate::Response::Http(
@@ -92,31 +73,356 @@ pub fn serve_static(ctx: &crate::Context) -> Result<crate::Response, failure::Er
 }
 
 lazy_static! {
-    pub static ref ELM: std::io::Result<Vec<u8>> = read_static(PrefixSuffix::Elm);
-    pub static ref ELM_GZ: std::io::Result<Vec<u8>> = read_static(PrefixSuffix::ElmGz);
-    pub static ref ELM_BR: std::io::Result<Vec<u8>> = read_static(PrefixSuffix::ElmBr);
-    pub static ref SW: std::io::Result<Vec<u8>> = read_static(PrefixSuffix::Sw);
+    // REALM_STATIC_MAX_AGE="*.css=86400,*.woff2=31536000,/static/img/*=604800"
+    pub static ref MAX_AGE_RULES: Result<Vec<(String, u64)>, failure::Error> =
+        parse_max_age_rules(
+            std::env::var("REALM_STATIC_MAX_AGE")
+                .unwrap_or_else(|_| "".to_string())
+                .as_str()
+        );
+    static ref ETAGS: antidote::Mutex<
+        std::collections::HashMap<String, (u64, std::time::SystemTime, String)>,
+    > = antidote::Mutex::new(std::collections::HashMap::new());
 }
 
-fn read_static(pre_suffix: PrefixSuffix) -> std::io::Result<Vec<u8>> {
-    let proj_dir = std::env::current_dir().map_err(|e| {
-        println!("read_static_err: {}", e.to_string());
-        e
-    })?;
+// files whose etag is cached, the cache is cleared when it is full
+const MAX_ETAGS: usize = 10_000;
+
+fn parse_max_age_rules(rules: &str) -> Result<Vec<(String, u64)>, failure::Error> {
+    rules
+        .split(',')
+        .filter(|r| !r.trim().is_empty())
+        .map(|r| {
+            let mut parts = r.splitn(2, '=');
+            match (parts.next(), parts.next().map(|v| v.trim().parse())) {
+                (Some(pattern), Some(Ok(age))) => Ok((pattern.trim().to_string(), age)),
+                _ => Err(format_err!("REALM_STATIC_MAX_AGE has invalid rule: {}", r)),
+            }
+        })
+        .collect()
+}
+
+// called at startup by realm::env::init(), an invalid rule is otherwise ignored
+pub fn init() -> Result<(), failure::Error> {
+    match MAX_AGE_RULES.as_ref() {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format_err!("{}", e)),
+    }
+}
+
+fn cache_control(path: &str) -> String {
+    if path.contains(".hashed-") {
+        return "immutable, public, max-age=3600000000".to_string();
+    }
+
+    // first matching rule wins
+    let rules = match MAX_AGE_RULES.as_ref() {
+        Ok(rules) => rules,
+        Err(_) => return "".to_string(),
+    };
+    match rules.iter().find(|(pattern, _)| glob_match(pattern, path)) {
+        Some((_, age)) => format!("public, max-age={}", age),
+        None => "".to_string(),
+    }
+}
+
+// `*` matches any run of characters, `?` matches exactly one, a pattern
+// without `/` is matched against the file name only
+fn glob_match(pattern: &str, path: &str) -> bool {
+    fn matches(p: &[char], s: &[char]) -> bool {
+        match p.split_first() {
+            None => s.is_empty(),
+            Some((&'*', rest)) => (0..=s.len()).any(|i| matches(rest, &s[i..])),
+            Some((&'?', rest)) => !s.is_empty() && matches(rest, &s[1..]),
+            Some((c, rest)) => s.first() == Some(c) && matches(rest, &s[1..]),
+        }
+    }
+
+    let target = if pattern.contains('/') {
+        path
+    } else {
+        path.rsplit('/').next().unwrap_or(path)
+    };
+
+    matches(
+        &pattern.chars().collect::<Vec<_>>(),
+        &target.chars().collect::<Vec<_>>(),
+    )
+}
+
+// content of elm and sw bundles, with etag computed once when it is read
+#[derive(Clone)]
+pub struct Bundle {
+    pub content: Vec<u8>,
+    pub etag: String,
+}
+
+enum Source {
+    Memory(Bundle),
+    Embedded(&'static crate::embed::Asset),
+    File {
+        src: String,
+        file: std::fs::File,
+        len: u64,
+        modified: Option<std::time::SystemTime>,
+    },
+}
+
+impl Source {
+    fn len(&self) -> u64 {
+        match self {
+            Source::Memory(bundle) => bundle.content.len() as u64,
+            Source::Embedded(asset) => asset.content.len() as u64,
+            Source::File { len, .. } => *len,
+        }
+    }
+
+    fn last_modified(&self) -> Option<String> {
+        match self {
+            Source::File {
+                modified: Some(modified),
+                ..
+            } => Some(crate::utils::http_date(*modified)),
+            _ => None,
+        }
+    }
+
+    // strong etag of the content, for files it is cached till the file changes
+    fn etag(&self) -> Result<String, failure::Error> {
+        use sha2::Digest;
+
+        match self {
+            Source::Memory(bundle) => Ok(bundle.etag.clone()),
+            Source::Embedded(asset) => Ok(asset_etag(asset)),
+            Source::File {
+                src,
+                file,
+                len,
+                modified,
+            } => {
+                let modified = modified.unwrap_or(std::time::UNIX_EPOCH);
+                if let Some((l, m, etag)) = ETAGS.lock().get(src) {
+                    if l == len && *m == modified {
+                        return Ok(etag.to_string());
+                    }
+                }
+
+                let mut hasher = sha2::Sha256::new();
+                std::io::copy(&mut std::io::Read::take(file, *len), &mut hasher)?;
+                let etag = to_etag(hasher.finalize());
+                let mut etags = ETAGS.lock();
+                if etags.len() >= MAX_ETAGS {
+                    etags.clear();
+                }
+                etags.insert(src.to_string(), (*len, modified, etag.clone()));
+                Ok(etag)
+            }
+        }
+    }
+}
+
+fn to_etag(digest: impl std::fmt::LowerHex) -> String {
+    format!("\"{}\"", &format!("{:x}", digest)[..16])
+}
+
+// hash is computed at build time by realm_macros::embed_assets!()
+fn asset_etag(asset: &crate::embed::Asset) -> String {
+    format!("\"{}\"", &asset.hash[..16])
+}
+
+fn static_source(src: &str) -> Result<Source, failure::Error> {
+    if src.contains(".hashed-") {
+        if let Some(resolved) = crate::assets::get_manifest().resolve(src) {
+            return static_source(resolved.as_str());
+        }
+    }
+
+    if is_hashed_bundle(src) {
+        return Ok(Source::Memory(hashed_bundle(src)?));
+    }
+
+    if crate::embed::is_embedded() {
+        return match crate::embed::get(src) {
+            Some(asset) => Ok(Source::Embedded(asset)),
+            None => Err(failure::err_msg("not embedded")),
+        };
+    }
+
+    let file = std::fs::File::open(static_path(src)?)?;
+    let metadata = file.metadata()?;
+    Ok(Source::File {
+        src: src.to_string(),
+        len: metadata.len(),
+        modified: metadata.modified().ok(),
+        file,
+    })
+}
+
+fn etag_matches(header: &str, etag: &str) -> bool {
+    header.trim() == "*"
+        || header
+            .split(',')
+            .any(|v| v.trim().trim_start_matches("W/") == etag)
+}
+
+// rfc7232: If-None-Match wins over If-Modified-Since when both are present
+fn not_modified(ctx: &crate::Context, etag: &str, last_modified: &Option<String>) -> bool {
+    if let Some(inm) = ctx.get_header_string(http::header::IF_NONE_MATCH) {
+        return etag_matches(inm.as_str(), etag);
+    }
+
+    match (
+        ctx.get_header_string(http::header::IF_MODIFIED_SINCE),
+        last_modified,
+    ) {
+        (Some(since), Some(modified)) => match (
+            chrono::DateTime::parse_from_rfc2822(since.trim()),
+            chrono::DateTime::parse_from_rfc2822(modified),
+        ) {
+            (Ok(since), Ok(modified)) => modified <= since,
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+// If-Range lets a client resume a download only if the file has not changed
+// since it got the first part, else it must be sent the whole file again
+fn if_range_matches(ctx: &crate::Context, etag: &str, last_modified: &Option<String>) -> bool {
+    let if_range = match ctx.get_header_string(http::header::IF_RANGE) {
+        Some(v) => v,
+        None => return true,
+    };
+    let if_range = if_range.trim();
+
+    // weak etags can not be used with If-Range
+    if_range == etag || Some(if_range) == last_modified.as_deref()
+}
+
+fn serve_source(
+    ctx: &crate::Context,
+    source: Source,
+    cache_control: &str,
+    mime: &str,
+    encoding: Option<&str>,
+    vary: bool,
+) -> Result<crate::Response, failure::Error> {
+    use crate::stream::Range;
+
+    let len = source.len();
+    let etag = source.etag()?;
+    let last_modified = source.last_modified();
+
+    let mut builder = http::Response::builder();
+    builder
+        .header("Cache-Control", cache_control)
+        .header("ETag", etag.as_str());
+    if let Some(ref last_modified) = last_modified {
+        builder.header("Last-Modified", last_modified.as_str());
+    }
+    if vary {
+        builder.header("Vary", "Accept-Encoding");
+    }
+
+    if not_modified(ctx, etag.as_str(), &last_modified) {
+        observer::log("not_modified");
+        return Ok(crate::Response::Http(
+            builder.status(http::StatusCode::NOT_MODIFIED).body(vec![])?,
+        ));
+    }
+
+    // a 304 has no body, so no Content-Encoding
+    if let Some(encoding) = encoding {
+        builder.header("Content-Encoding", encoding);
+    }
+
+    builder
+        .header("Content-Type", mime)
+        .header("Service-Worker-Allowed", "/")
+        .header("Accept-Ranges", "bytes");
+
+    let range = if if_range_matches(ctx, etag.as_str(), &last_modified) {
+        Range::parse(ctx.get_header_string(http::header::RANGE).as_deref(), len)
+    } else {
+        Range::Full
+    };
+
+    let (start, count) = match range {
+        Range::Full => {
+            builder.status(http::StatusCode::OK);
+            (0, len)
+        }
+        Range::Partial { start, end } => {
+            observer::observe_string("range", format!("{}-{}", start, end).as_str());
+            builder
+                .status(http::StatusCode::PARTIAL_CONTENT)
+                .header("Content-Range", format!("bytes {}-{}/{}", start, end, len));
+            (start, end - start + 1)
+        }
+        Range::Unsatisfiable => {
+            return Ok(crate::Response::Http(
+                builder
+                    .status(http::StatusCode::RANGE_NOT_SATISFIABLE)
+                    .header("Content-Range", format!("bytes */{}", len))
+                    .body(vec![])?,
+            ));
+        }
+    };
+    builder.header("Content-Length", count.to_string());
+
+    match source {
+        Source::Memory(bundle) => Ok(crate::Response::Http(
+            builder.body(bundle.content[start as usize..(start + count) as usize].to_vec())?,
+        )),
+        Source::Embedded(asset) => Ok(crate::Response::Http(
+            builder.body(asset.content[start as usize..(start + count) as usize].to_vec())?,
+        )),
+        Source::File { file, .. } => Ok(crate::Response::Stream(
+            builder.body(crate::stream::Body::from_file(file, start, count)?)?,
+        )),
+    }
+}
+
+lazy_static! {
+    pub static ref ELM: std::io::Result<Bundle> = read_static(PrefixSuffix::Elm);
+    pub static ref ELM_GZ: std::io::Result<Bundle> = read_static(PrefixSuffix::ElmGz);
+    pub static ref ELM_BR: std::io::Result<Bundle> = read_static(PrefixSuffix::ElmBr);
+    pub static ref SW: std::io::Result<Bundle> = read_static(PrefixSuffix::Sw);
+}
+
+fn read_static(pre_suffix: PrefixSuffix) -> std::io::Result<Bundle> {
+    use sha2::Digest;
+
     let (prefix, suffix) = pre_suffix.to_str();
-    let path = proj_dir.join(format!(
-        "static/{}.{}.js{}",
+    let name = format!(
+        "/static/{}.{}.js{}",
         prefix,
         crate::page::read_current(),
         suffix
-    ));
-    std::fs::read(path.as_path()).map_err(|e| {
+    );
+    if let Some(asset) = crate::embed::get(name.as_str()) {
+        return Ok(Bundle {
+            content: asset.content.to_vec(),
+            etag: asset_etag(asset),
+        });
+    }
+
+    let proj_dir = std::env::current_dir().map_err(|e| {
+        println!("read_static_err: {}", e.to_string());
+        e
+    })?;
+    let path = proj_dir.join(&name[1..]);
+    let content = std::fs::read(path.as_path()).map_err(|e| {
         println!(
             "path_not_found: {:?}, err: {}",
             path.as_os_str(),
             e.to_string()
         );
         e
+    })?;
+    Ok(Bundle {
+        etag: to_etag(sha2::Sha256::digest(content.as_slice())),
+        content,
     })
 }
 
@@ -138,7 +444,7 @@ impl PrefixSuffix {
     }
 }
 
-fn get_static(pre_suffix: PrefixSuffix) -> Result<Vec<u8>, failure::Error> {
+fn get_static(pre_suffix: PrefixSuffix) -> Result<Bundle, failure::Error> {
     if cfg!(debug_assertions) {
         read_static(pre_suffix).map_err(|e| failure::err_msg(format!("{}", e)))
     } else {
@@ -154,9 +460,11 @@ fn get_static(pre_suffix: PrefixSuffix) -> Result<Vec<u8>, failure::Error> {
     }
 }
 
-pub fn static_content(src: &str) -> Result<Vec<u8>, failure::Error> {
-    use std::io::Read;
+fn is_hashed_bundle(src: &str) -> bool {
+    src.starts_with("/static/elm.hashed-") || src.starts_with("/static/sw.hashed-")
+}
 
+fn hashed_bundle(src: &str) -> Result<Bundle, failure::Error> {
     if src.starts_with("/static/elm.hashed-") {
         if !src.contains(crate::page::CURRENT.as_str()) {
             observer::log("fetched latest elm when old was requested");
@@ -183,14 +491,63 @@ pub fn static_content(src: &str) -> Result<Vec<u8>, failure::Error> {
         });
     }
 
-    let path = std::fs::canonicalize(".".to_string() + src)?;
-    if !path.starts_with(std::env::current_dir()?) {
-        return Err(failure::err_msg("outside file rejected"));
+    Err(failure::err_msg("not found"))
+}
+
+pub fn static_content(src: &str) -> Result<Vec<u8>, failure::Error> {
+    use std::io::Read;
+
+    if is_hashed_bundle(src) {
+        return Ok(hashed_bundle(src)?.content);
+    }
+
+    if let Some(asset) = crate::embed::get(src) {
+        return Ok(asset.content.to_vec());
     }
 
-    let mut file = std::fs::File::open(&path)?;
+    let mut file = std::fs::File::open(static_path(src)?)?;
 
     let mut content = Vec::new();
     file.read_to_end(&mut content)?;
     Ok(content)
 }
+
+fn static_path(src: &str) -> Result<std::path::PathBuf, failure::Error> {
+    let path = std::fs::canonicalize(".".to_string() + src)?;
+    if !path.starts_with(std::env::current_dir()?) {
+        return Err(failure::err_msg("outside file rejected"));
+    }
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn glob_match() {
+        assert!(super::glob_match("*.css", "/static/a/main.css"));
+        assert!(!super::glob_match("*.css", "/static/a/main.css.map"));
+        assert!(super::glob_match("/static/img/*", "/static/img/logo.png"));
+        assert!(!super::glob_match("/static/img/*", "/static/logo.png"));
+        assert!(super::glob_match("logo.???", "/static/logo.png"));
+    }
+
+    #[test]
+    fn parse_max_age_rules() {
+        assert!(super::parse_max_age_rules("").unwrap().is_empty());
+        assert!(super::parse_max_age_rules("*.css=soon").is_err());
+        assert_eq!(
+            super::parse_max_age_rules("*.css=60, /static/img/*=3600").unwrap(),
+            vec![
+                ("*.css".to_string(), 60),
+                ("/static/img/*".to_string(), 3600)
+            ]
+        );
+    }
+
+    #[test]
+    fn etag_matches() {
+        assert!(super::etag_matches("*", "\"abc\""));
+        assert!(super::etag_matches("\"x\", W/\"abc\"", "\"abc\""));
+        assert!(!super::etag_matches("\"x\"", "\"abc\""));
+    }
+}
@@ -0,0 +1,195 @@
+// The html page around every Response::Page is rendered by an askama template,
+// templates/shell.html, instead of replacing __realm_*__ strings in index.html.
+// Apps that want a different page write their own template, which gets the
+// same typed slots, and register it at startup:
+//
+//     #[derive(askama::Template)]
+//     #[template(path = "shell.html")]
+//     struct Shell<'a> {
+//         s: &'a realm::shell::Slots<'a>,
+//     }
+//
+//     fn main() {
+//         realm::shell::register(|s| askama::Template::render(&Shell { s }));
+//         ...
+//     }
+//
+// A typo in slot name is a compile error in the app. Everything except title
+// is already html, so it has to be used with `|safe`, see templates/shell.html.
+// Every <script> in the template needs nonce="{{ s.nonce }}", else it is
+// blocked when REALM_CSP uses {nonce}.
+
+pub struct Script {
+    pub src: Option<String>,
+    pub integrity: Option<String>,
+    pub module: bool,
+    pub content: Option<String>,
+}
+
+impl Script {
+    // integrity is picked from the asset manifest for files in /static/
+    pub fn src(src: &str) -> Script {
+        Script {
+            integrity: crate::assets::get_manifest()
+                .integrity_for(src)
+                .map(ToString::to_string),
+            src: Some(src.to_string()),
+            module: false,
+            content: None,
+        }
+    }
+
+    pub fn inline(content: String) -> Script {
+        Script {
+            src: None,
+            integrity: None,
+            module: false,
+            content: Some(content),
+        }
+    }
+
+    pub fn module(content: String) -> Script {
+        Script {
+            module: true,
+            ..Script::inline(content)
+        }
+    }
+}
+
+pub struct Slots<'a> {
+    // id of the request language, eg "en"
+    pub lang: &'a str,
+    // plain text, escaped by the template
+    pub title: &'a str,
+    // meta tags from HTMLMeta
+    pub head: String,
+    // PageSpec json, already escaped for use inside <script>, None for crawlers
+    pub data: Option<String>,
+    // server rendered page, only sent to crawlers
+    pub body: &'a str,
+    // empty for crawlers
+    pub scripts: Vec<Script>,
+    // for Content-Security-Policy, must be added to every inline script
+    pub nonce: &'a str,
+}
+
+#[derive(askama::Template)]
+#[template(path = "shell.html")]
+pub struct DefaultShell<'a> {
+    pub s: &'a Slots<'a>,
+}
+
+pub type Shell = fn(&Slots) -> askama::Result<String>;
+
+fn default_shell(s: &Slots) -> askama::Result<String> {
+    askama::Template::render(&DefaultShell { s })
+}
+
+lazy_static! {
+    static ref SHELL: antidote::RwLock<Shell> = antidote::RwLock::new(default_shell);
+}
+
+pub fn register(shell: Shell) {
+    *SHELL.write() = shell;
+}
+
+pub fn render(slots: &Slots) -> Result<String, failure::Error> {
+    let shell = *SHELL.read();
+    shell(slots).map_err(|e| failure::format_err!("failed to render shell: {}", e))
+}
+
+// elm is the url of the elm bundle, iframe uses a different one
+pub fn scripts(elm: &str) -> Result<Vec<Script>, failure::Error> {
+    let current = crate::page::CURRENT.trim();
+
+    Ok(vec![
+        Script::inline(crate::page::get_ftd_js()?),
+        Script::src(elm),
+        Script::module(format!(
+            r#"
+      import init, {{ create }} from '/static/{}/ftd_rt.js';
+
+      async function run() {{
+        await init();
+
+        function inner() {{
+          if (!window.realm) {{
+              window.requestAnimationFrame(inner);
+              return;
+          }}
+
+          realm(create);
+        }}
+
+        window.requestAnimationFrame(inner);
+      }}
+
+      run();
+    "#,
+            current
+        )),
+    ])
+}
+
+pub fn elm_url() -> String {
+    format!("/static/{}/elm.js", crate::page::CURRENT.trim())
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn default_shell() {
+        let slots = super::Slots {
+            lang: "en",
+            title: "<hello>",
+            head: "<meta name=\"x\" content=\"y\">".to_string(),
+            data: Some("{}".to_string()),
+            body: "",
+            scripts: vec![
+                super::Script::inline("var x = 1;".to_string()),
+                super::Script {
+                    src: Some("/static/elm.js".to_string()),
+                    integrity: Some("sha256-abc".to_string()),
+                    module: false,
+                    content: None,
+                },
+            ],
+            nonce: "n",
+        };
+
+        let html = super::default_shell(&slots).unwrap();
+        assert!(html.contains("<html lang=\"en\">"));
+        assert!(html.contains("<title>&lt;hello&gt;</title>"));
+        assert!(html.contains("<meta name=\"x\" content=\"y\">"));
+        assert!(html.contains("<script id=\"data\" type=\"application/json\" nonce=\"n\">"));
+        assert!(html.contains("<script nonce=\"n\">var x = 1;</script>"));
+        assert!(html.contains("integrity=\"sha256-abc\" crossorigin=\"anonymous\"></script>"));
+    }
+
+    #[test]
+    fn elm_url_integrity() {
+        let current = crate::page::CURRENT.trim();
+        let served = crate::assets::Manifest::from_hashes(vec![
+            (
+                format!("{}/elm.js", current),
+                "a94a8fe5ccb19ba61c4c0873d391e987982fbbd3a94a8fe5ccb19ba61c4c0873".to_string(),
+            ),
+            (
+                format!("elm.{}.js", current),
+                "0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef".to_string(),
+            ),
+        ]);
+        assert_eq!(
+            served.integrity_for(super::elm_url().as_str()),
+            Some("sha256-qUqP5cyxm6YcTAhz05Hph5gvu9OpSo/lzLGbphxMCHM=")
+        );
+
+        // static/elm.<current>.js is not what is served at elm_url()
+        let manifest = crate::assets::Manifest::from_hashes(vec![(
+            format!("elm.{}.js", current),
+            "a94a8fe5ccb19ba61c4c0873d391e987982fbbd3a94a8fe5ccb19ba61c4c0873".to_string(),
+        )]);
+        assert_eq!(manifest.integrity_for(super::elm_url().as_str()), None);
+        assert_eq!(manifest.integrity_for("/static/v1/other.js"), None);
+    }
+}
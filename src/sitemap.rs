@@ -0,0 +1,330 @@
+// /sitemap.xml and /robots.txt are generated from url sources registered by
+// the app at startup:
+//
+//     realm::sitemap::add_static(&["/", "/about/"]);
+//     realm::sitemap::register("articles", || {
+//         let conn = realm::base::pg::connection();
+//         Ok(Box::new(articles(&conn)?.into_iter().map(|a| {
+//             realm::sitemap::Entry::new(a.url().as_str())
+//                 .lastmod(a.updated_on)
+//                 .no_index(a.is_draft)
+//         })))
+//     });
+//
+// /sitemap.xml is a sitemap index pointing to /sitemap-1.xml, /sitemap-2.xml
+// etc, each with at most REALM_SITEMAP_PAGE_SIZE (default 50000) urls, the
+// number of pages is recounted every 10 minutes. Entries marked no_index are
+// left out, sources know this as they also render the pages with
+// HTMLMeta.no_index. If no source is registered /robots.txt is served from
+// static folder like before.
+
+use crate::utils::escape_xml;
+use chrono::{DateTime, Utc};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChangeFreq {
+    Always,
+    Hourly,
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+    Never,
+}
+
+impl ChangeFreq {
+    fn as_str(self) -> &'static str {
+        match self {
+            ChangeFreq::Always => "always",
+            ChangeFreq::Hourly => "hourly",
+            ChangeFreq::Daily => "daily",
+            ChangeFreq::Weekly => "weekly",
+            ChangeFreq::Monthly => "monthly",
+            ChangeFreq::Yearly => "yearly",
+            ChangeFreq::Never => "never",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    // path, eg /about/, or full url
+    pub loc: String,
+    pub lastmod: Option<DateTime<Utc>>,
+    pub changefreq: Option<ChangeFreq>,
+    // 0.0 to 1.0
+    pub priority: Option<f32>,
+    pub no_index: bool,
+}
+
+impl Entry {
+    pub fn new(loc: &str) -> Self {
+        Entry {
+            loc: loc.to_string(),
+            lastmod: None,
+            changefreq: None,
+            priority: None,
+            no_index: false,
+        }
+    }
+
+    pub fn lastmod(mut self, lastmod: DateTime<Utc>) -> Self {
+        self.lastmod = Some(lastmod);
+        self
+    }
+
+    pub fn changefreq(mut self, changefreq: ChangeFreq) -> Self {
+        self.changefreq = Some(changefreq);
+        self
+    }
+
+    pub fn priority(mut self, priority: f32) -> Self {
+        self.priority = Some(priority);
+        self
+    }
+
+    pub fn no_index(mut self, no_index: bool) -> Self {
+        self.no_index = no_index;
+        self
+    }
+}
+
+pub type Entries = Box<dyn Iterator<Item = Entry>>;
+pub type Source = Box<dyn Fn() -> Result<Entries, failure::Error> + Send + Sync>;
+
+lazy_static! {
+    static ref SOURCES: antidote::RwLock<Vec<(String, Source)>> = antidote::RwLock::new(vec![]);
+    static ref DISALLOW: antidote::RwLock<Vec<String>> = antidote::RwLock::new(vec![]);
+    static ref PAGE_SIZE: usize = std::env::var("REALM_SITEMAP_PAGE_SIZE")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|v| *v > 0)
+        .unwrap_or(50_000);
+    // (when, number of entries) for /sitemap.xml, counting reads every source
+    static ref COUNT: antidote::Mutex<Option<(std::time::Instant, usize)>> =
+        antidote::Mutex::new(None);
+}
+
+// how long the entry count used by /sitemap.xml is reused
+const COUNT_TTL: std::time::Duration = std::time::Duration::from_secs(600);
+
+pub fn register<F>(name: &str, source: F)
+where
+    F: Fn() -> Result<Entries, failure::Error> + Send + Sync + 'static,
+{
+    SOURCES.write().push((name.to_string(), Box::new(source)));
+    *COUNT.lock() = None;
+}
+
+pub fn add_static(paths: &[&str]) {
+    let entries: Vec<Entry> = paths.iter().map(|p| Entry::new(p)).collect();
+    register("static", move || Ok(Box::new(entries.clone().into_iter())));
+}
+
+// added as Disallow: to robots.txt
+pub fn disallow(path: &str) {
+    DISALLOW.write().push(path.to_string());
+}
+
+pub fn is_enabled() -> bool {
+    !SOURCES.read().is_empty()
+}
+
+fn entries() -> Result<impl Iterator<Item = Entry>, failure::Error> {
+    let mut all: Vec<Entries> = vec![];
+    for (name, source) in SOURCES.read().iter() {
+        all.push(source().map_err(|e| format_err!("sitemap source {}: {}", name, e))?);
+    }
+
+    Ok(all.into_iter().flatten().filter(|e| !e.no_index))
+}
+
+fn absolute(site: &str, loc: &str) -> String {
+    if loc.starts_with('/') {
+        format!("{}{}", site.trim_end_matches('/'), loc)
+    } else {
+        loc.to_string()
+    }
+}
+
+pub fn urlset(site: &str, entries: impl Iterator<Item = Entry>) -> String {
+    let mut xml = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+                   <urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n"
+        .to_string();
+    for e in entries {
+        xml.push_str("  <url>\n");
+        xml.push_str(&format!(
+            "    <loc>{}</loc>\n",
+            escape_xml(absolute(site, e.loc.as_str()).as_str())
+        ));
+        if let Some(lastmod) = e.lastmod {
+            xml.push_str(&format!(
+                "    <lastmod>{}</lastmod>\n",
+                lastmod.to_rfc3339()
+            ));
+        }
+        if let Some(changefreq) = e.changefreq {
+            xml.push_str(&format!(
+                "    <changefreq>{}</changefreq>\n",
+                changefreq.as_str()
+            ));
+        }
+        if let Some(priority) = e.priority {
+            xml.push_str(&format!(
+                "    <priority>{:.1}</priority>\n",
+                priority.max(0.0).min(1.0)
+            ));
+        }
+        xml.push_str("  </url>\n");
+    }
+    xml.push_str("</urlset>\n");
+    xml
+}
+
+pub fn sitemap_index(site: &str, pages: usize) -> String {
+    let mut xml = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+                   <sitemapindex xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n"
+        .to_string();
+    for page in 1..=pages.max(1) {
+        xml.push_str(&format!(
+            "  <sitemap>\n    <loc>{}</loc>\n  </sitemap>\n",
+            escape_xml(absolute(site, format!("/sitemap-{}.xml", page).as_str()).as_str())
+        ));
+    }
+    xml.push_str("</sitemapindex>\n");
+    xml
+}
+
+pub fn robots(site: &str, disallow: &[String]) -> String {
+    let mut txt = "User-agent: *\n".to_string();
+    if disallow.is_empty() {
+        txt.push_str("Disallow:\n");
+    }
+    for path in disallow.iter() {
+        txt.push_str(&format!("Disallow: {}\n", path));
+    }
+    txt.push_str(&format!("\nSitemap: {}\n", absolute(site, "/sitemap.xml")));
+    txt
+}
+
+// page number from /sitemap-<n>.xml
+fn page_number(path: &str) -> Option<usize> {
+    if !path.starts_with("/sitemap-") || !path.ends_with(".xml") {
+        return None;
+    }
+    path["/sitemap-".len()..path.len() - ".xml".len()]
+        .parse()
+        .ok()
+        .filter(|n| *n > 0)
+}
+
+pub fn is_sitemap_url(path: &str) -> bool {
+    path == "/sitemap.xml" || page_number(path).is_some()
+}
+
+fn count() -> Result<usize, failure::Error> {
+    if let Some((at, count)) = *COUNT.lock() {
+        if at.elapsed() < COUNT_TTL {
+            return Ok(count);
+        }
+    }
+
+    let count = entries()?.count();
+    *COUNT.lock() = Some((std::time::Instant::now(), count));
+    Ok(count)
+}
+
+fn not_found(path: &str) -> failure::Error {
+    crate::Error::PageNotFound {
+        message: format!("no sitemap page: {}", path),
+    }
+    .into()
+}
+
+fn xml(ctx: &crate::Context, body: String) -> Result<crate::Response, failure::Error> {
+    ctx.header(http::header::CONTENT_TYPE, "application/xml; charset=utf-8");
+    Ok(crate::Response::Http(ctx.response(body.into_bytes())?))
+}
+
+pub fn serve_sitemap(ctx: &crate::Context) -> Result<crate::Response, failure::Error> {
+    let site = crate::env::site_url();
+    let path = ctx.url.path();
+
+    if path == "/sitemap.xml" {
+        let pages = (count()? + *PAGE_SIZE - 1) / *PAGE_SIZE;
+        return xml(ctx, sitemap_index(site.as_str(), pages));
+    }
+
+    let page = match page_number(path) {
+        Some(p) => p,
+        None => return Err(not_found(path)),
+    };
+    let mut entries = entries()?
+        .skip((page - 1) * *PAGE_SIZE)
+        .take(*PAGE_SIZE)
+        .peekable();
+    if page > 1 && entries.peek().is_none() {
+        return Err(not_found(path));
+    }
+    xml(ctx, urlset(site.as_str(), entries))
+}
+
+pub fn serve_robots(ctx: &crate::Context) -> Result<crate::Response, failure::Error> {
+    if !is_enabled() {
+        return crate::serve_static::serve_static(ctx);
+    }
+
+    ctx.header(http::header::CONTENT_TYPE, "text/plain; charset=utf-8");
+    Ok(crate::Response::Http(ctx.response(
+        robots(crate::env::site_url().as_str(), &DISALLOW.read()).into_bytes(),
+    )?))
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn urlset() {
+        let entries = vec![
+            super::Entry::new("/"),
+            super::Entry::new("/a/?x=1&y=2")
+                .changefreq(super::ChangeFreq::Daily)
+                .priority(0.8),
+            super::Entry::new("https://other.com/b/"),
+        ];
+
+        assert_eq!(
+            super::urlset("https://example.com/", entries.into_iter()),
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+             <urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n  \
+             <url>\n    <loc>https://example.com/</loc>\n  </url>\n  \
+             <url>\n    <loc>https://example.com/a/?x=1&amp;y=2</loc>\n    \
+             <changefreq>daily</changefreq>\n    <priority>0.8</priority>\n  </url>\n  \
+             <url>\n    <loc>https://other.com/b/</loc>\n  </url>\n\
+             </urlset>\n"
+        );
+    }
+
+    #[test]
+    fn sitemap_index() {
+        let xml = super::sitemap_index("https://example.com", 2);
+        assert!(xml.contains("<loc>https://example.com/sitemap-1.xml</loc>"));
+        assert!(xml.contains("<loc>https://example.com/sitemap-2.xml</loc>"));
+        assert!(!xml.contains("sitemap-3.xml"));
+    }
+
+    #[test]
+    fn page_number() {
+        assert_eq!(super::page_number("/sitemap-2.xml"), Some(2));
+        assert_eq!(super::page_number("/sitemap-0.xml"), None);
+        assert_eq!(super::page_number("/sitemap.xml"), None);
+        assert_eq!(super::page_number("/sitemap-x.xml"), None);
+    }
+
+    #[test]
+    fn robots() {
+        assert_eq!(
+            super::robots("https://example.com", &["/admin/".to_string()]),
+            "User-agent: *\nDisallow: /admin/\n\nSitemap: https://example.com/sitemap.xml\n"
+        );
+    }
+}
@@ -0,0 +1,176 @@
+const CHUNK_SIZE: u64 = 64 * 1024;
+
+pub struct Body {
+    pub len: Option<u64>,
+    chunks: Box<dyn Iterator<Item = std::io::Result<Vec<u8>>> + Send>,
+}
+
+impl Body {
+    pub fn from_iter<I>(len: Option<u64>, iter: I) -> Self
+    where
+        I: Iterator<Item = std::io::Result<Vec<u8>>> + Send + 'static,
+    {
+        Body {
+            len,
+            chunks: Box::new(iter),
+        }
+    }
+
+    pub fn from_file(mut file: std::fs::File, start: u64, len: u64) -> std::io::Result<Self> {
+        use std::io::{Read, Seek};
+
+        file.seek(std::io::SeekFrom::Start(start))?;
+        Ok(Self::from_iter(
+            Some(len),
+            FileChunks {
+                file: file.take(len),
+            },
+        ))
+    }
+
+    pub fn into_bytes(self) -> std::io::Result<Vec<u8>> {
+        let mut content = Vec::with_capacity(self.len.unwrap_or(0) as usize);
+        for chunk in self.chunks {
+            content.extend(chunk?);
+        }
+        Ok(content)
+    }
+
+    // file reads block, so chunks are read on THREAD_POOL and handed to hyper
+    // through a channel instead of on the reactor thread. The channel holds one
+    // chunk, so reading waits for a slow client without holding a pool thread.
+    pub fn into_hyper(self) -> hyper::Body {
+        use futures::{Future, Sink, Stream};
+
+        let (sender, receiver) = futures::sync::mpsc::channel(1);
+        crate::THREAD_POOL
+            .spawn(
+                sender
+                    .sink_map_err(|_| ())
+                    .send_all(futures::stream::iter_ok::<_, ()>(self.chunks))
+                    .map(|_| ()),
+            )
+            .forget();
+
+        hyper::Body::wrap_stream(receiver.then(|r| match r {
+            Ok(chunk) => chunk,
+            Err(()) => Err(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                "file read stopped",
+            )),
+        }))
+    }
+}
+
+struct FileChunks {
+    file: std::io::Take<std::fs::File>,
+}
+
+impl Iterator for FileChunks {
+    type Item = std::io::Result<Vec<u8>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        use std::io::Read;
+
+        let mut chunk = Vec::with_capacity(CHUNK_SIZE as usize);
+        match (&mut self.file).take(CHUNK_SIZE).read_to_end(&mut chunk) {
+            Ok(0) => None,
+            Ok(_) => Some(Ok(chunk)),
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Range {
+    Full,
+    Partial { start: u64, end: u64 },
+    Unsatisfiable,
+}
+
+impl Range {
+    // only single byte ranges are supported, for anything else we send the
+    // whole content, which rfc7233 allows
+    pub fn parse(header: Option<&str>, len: u64) -> Range {
+        let spec = match header.map(str::trim) {
+            Some(h) if h.starts_with("bytes=") => &h["bytes=".len()..],
+            _ => return Range::Full,
+        };
+
+        if spec.contains(',') {
+            return Range::Full;
+        }
+
+        let mut parts = spec.splitn(2, '-');
+        let (first, last) = match (parts.next(), parts.next()) {
+            (Some(f), Some(l)) => (f.trim(), l.trim()),
+            _ => return Range::Full,
+        };
+
+        let (start, end) = if first.is_empty() {
+            // suffix range: last N bytes
+            match last.parse::<u64>() {
+                Ok(0) => return Range::Unsatisfiable,
+                Ok(n) if n >= len => (0, len.saturating_sub(1)),
+                Ok(n) => (len - n, len - 1),
+                Err(_) => return Range::Full,
+            }
+        } else {
+            let start = match first.parse::<u64>() {
+                Ok(s) => s,
+                Err(_) => return Range::Full,
+            };
+            let end = if last.is_empty() {
+                len.saturating_sub(1)
+            } else {
+                match last.parse::<u64>() {
+                    Ok(e) if e < start => return Range::Full,
+                    Ok(e) => std::cmp::min(e, len.saturating_sub(1)),
+                    Err(_) => return Range::Full,
+                }
+            };
+            (start, end)
+        };
+
+        if len == 0 || start >= len {
+            return Range::Unsatisfiable;
+        }
+
+        Range::Partial { start, end }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Range;
+
+    #[test]
+    fn range_parse() {
+        assert_eq!(Range::parse(None, 100), Range::Full);
+        assert_eq!(Range::parse(Some("items=0-10"), 100), Range::Full);
+        assert_eq!(Range::parse(Some("bytes=0-10,20-30"), 100), Range::Full);
+        assert_eq!(
+            Range::parse(Some("bytes=0-9"), 100),
+            Range::Partial { start: 0, end: 9 }
+        );
+        assert_eq!(
+            Range::parse(Some("bytes=90-"), 100),
+            Range::Partial { start: 90, end: 99 }
+        );
+        assert_eq!(
+            Range::parse(Some("bytes=90-200"), 100),
+            Range::Partial { start: 90, end: 99 }
+        );
+        assert_eq!(
+            Range::parse(Some("bytes=-10"), 100),
+            Range::Partial { start: 90, end: 99 }
+        );
+        assert_eq!(
+            Range::parse(Some("bytes=-200"), 100),
+            Range::Partial { start: 0, end: 99 }
+        );
+        assert_eq!(Range::parse(Some("bytes=100-"), 100), Range::Unsatisfiable);
+        assert_eq!(Range::parse(Some("bytes=-0"), 100), Range::Unsatisfiable);
+        assert_eq!(Range::parse(Some("bytes=10-5"), 100), Range::Full);
+    }
+}
@@ -3,8 +3,13 @@ pub fn is_realm_url(p: (&str, &http::Method)) -> bool {
         ("/storybook/", &http::Method::GET) => true,
         ("/storybook/poll/", &http::Method::GET) => true,
         ("/iframe/", &http::Method::GET) => true,
+        ("/realm/analytics/", &http::Method::GET) => true,
+        ("/realm/task/", &http::Method::GET) => true,
         ("/favicon.ico", &http::Method::GET) => true,
         ("/robots.txt", &http::Method::GET) => true,
+        (t, &http::Method::GET) if crate::sitemap::is_sitemap_url(t) => {
+            crate::sitemap::is_enabled()
+        }
         (t, _) if t.starts_with("/test/") => true,
         (t, &http::Method::GET) if t.starts_with("/static/") => true,
         _ => false,
@@ -39,12 +44,29 @@ where
             crate::rr::post(in_, id, title, description, base).map_err(Into::into)
         }
         ("/test/stop-recording/", _) => crate::rr::stop(in_).map_err(Into::into),
+        ("/test/activity/", &http::Method::GET) => {
+            crate::explorer::list(in_, &crate::explorer::Filter::new(input)?)
+        }
+        ("/test/activity/detail/", &http::Method::GET) => {
+            crate::explorer::detail(in_, input.required("id")?)
+        }
+        ("/test/activity/shapes/", &http::Method::GET) => {
+            crate::explorer::shapes(in_, input.optional("path")?)
+        }
+        ("/realm/analytics/", &http::Method::GET) => {
+            let (from, to) = (input.optional("from")?, input.optional("to")?);
+            crate::analytics::report(in_, from, to, input.optional("funnel")?)
+        }
+        ("/realm/task/", &http::Method::GET) => {
+            crate::worker::status_json(in_, input.required("id")?)
+        }
 
         ("/favicon.ico", &http::Method::GET) => {
             crate::serve_static::serve_static(in_.ctx).map_err(Into::into)
         }
-        ("/robots.txt", &http::Method::GET) => {
-            crate::serve_static::serve_static(in_.ctx).map_err(Into::into)
+        ("/robots.txt", &http::Method::GET) => crate::sitemap::serve_robots(in_.ctx),
+        (t, &http::Method::GET) if crate::sitemap::is_sitemap_url(t) => {
+            crate::sitemap::serve_sitemap(in_.ctx)
         }
         (t, &http::Method::GET) if t.starts_with("/static/") => {
             crate::serve_static::serve_static(in_.ctx).map_err(Into::into)
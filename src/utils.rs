@@ -32,6 +32,21 @@ pub fn set_cookie(name: &str, value: &str, age: i64) -> String {
     format!("{}={}; Max-Age={}; Path=/; {}", name, value, age, &domain)
 }
 
+pub fn http_date(t: std::time::SystemTime) -> String {
+    DateTime::<Utc>::from(t)
+        .format("%a, %d %b %Y %H:%M:%S GMT")
+        .to_string()
+}
+
+// for xml text and attribute values
+pub fn escape_xml(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
 pub fn get_slash_complete_path(path: &str) -> String {
     if path.ends_with('/') {
         path.to_string()
@@ -124,23 +124,7 @@ fn get_current() -> Result<String, failure::Error> {
         return Err(failure::err_msg("doit failed"));
     };
 
-    let output = std::process::Command::new("shasum")
-        .arg(
-            std::env::var("REALM_WATCHER_IFRAME")
-                .unwrap_or_else(|_| "static/iframe.js".to_string()),
-        )
-        .output()
-        .unwrap();
-
-    if !output.status.success() {
-        eprintln!("shasum failed");
-        eprintln!("stdout: {}", std::str::from_utf8(&output.stdout).unwrap());
-        eprintln!("stderr: {}", std::str::from_utf8(&output.stderr).unwrap());
-        return Err(failure::err_msg("shasum failed"));
-    };
-
-    Ok(std::str::from_utf8(&output.stdout)
-        .unwrap()
-        .trim()
-        .to_string())
+    let path =
+        std::env::var("REALM_WATCHER_IFRAME").unwrap_or_else(|_| "static/iframe.js".to_string());
+    Ok(crate::assets::hash(std::fs::read(path)?.as_slice()))
 }
@@ -1,94 +1,430 @@
 use diesel::prelude::*;
+use diesel::sql_types::{Integer, Nullable};
 
-#[derive(Queryable, serde::Deserialize)]
+// A failed task is retried after an exponential backoff with jitter, till it
+// has been tried REALM_TASK_MAX_ATTEMPTS (default 5) times, then it is marked
+// dead with the last error. Backoff before n-th retry is
+// REALM_TASK_BACKOFF_SECS (default 10) * 2^(n - 1), capped at
+// REALM_TASK_BACKOFF_MAX_SECS (default 3600), half of which is random.
+lazy_static! {
+    pub static ref REALM_TASK_MAX_ATTEMPTS: i32 = env_or("REALM_TASK_MAX_ATTEMPTS", 5);
+    pub static ref REALM_TASK_BACKOFF_SECS: i64 = env_or("REALM_TASK_BACKOFF_SECS", 10);
+    pub static ref REALM_TASK_BACKOFF_MAX_SECS: i64 = env_or("REALM_TASK_BACKOFF_MAX_SECS", 3600);
+}
+
+// A task is claimed by one worker by marking it in_progress till now +
+// REALM_TASK_LEASE_SECS (default 300). If the worker crashes, the task is
+// claimed again once the lease is over, so a task must finish within the lease.
+// Each worker process runs REALM_WORKER_THREADS (default 1) tasks in parallel.
+lazy_static! {
+    pub static ref REALM_TASK_LEASE_SECS: i64 = env_or("REALM_TASK_LEASE_SECS", 300);
+    pub static ref REALM_WORKER_THREADS: usize = env_or("REALM_WORKER_THREADS", 1);
+}
+
+// A task created with a key is not created again while a task with the same
+// key, created in last REALM_TASK_DEDUP_SECS (default 86400) or not yet
+// processed or dead, exists, the id of existing task is returned instead. The
+// handler gets the key in TASK_KEY_HEADER, see task_key().
+lazy_static! {
+    pub static ref REALM_TASK_DEDUP_SECS: i64 = env_or("REALM_TASK_DEDUP_SECS", 86400);
+}
+
+pub const TASK_KEY_HEADER: &str = "x-realm-task-key";
+
+// A worker with nothing to do waits till a task is created, or for at most
+// REALM_WORKER_POLL_SECS (default 2), after which it polls realm_task anyway.
+// Tasks created by a request in the same process wake it through an in memory
+// channel, from end_context() once the request's transaction is over. With the
+// postgres feature, creating a task also does NOTIFY realm_task, and run()
+// LISTENs on a separate connection to DATABASE_URL, so workers in other
+// processes wake up too.
+lazy_static! {
+    pub static ref REALM_WORKER_POLL_SECS: u64 = env_or("REALM_WORKER_POLL_SECS", 2);
+    static ref WAKE: (
+        crossbeam_channel::Sender<()>,
+        crossbeam_channel::Receiver<()>
+    ) = crossbeam_channel::bounded(1);
+}
+
+const CHANNEL: &str = "realm_task";
+
+fn parse_env<T: std::str::FromStr>(name: &str, default: T) -> crate::base::Result<T> {
+    match std::env::var(name) {
+        Ok(v) => v
+            .trim()
+            .parse()
+            .map_err(|_| format_err!("{} is {}, must be a number", name, v)),
+        Err(_) => Ok(default),
+    }
+}
+
+// a wrong value is reported by init() at startup, default is used after that
+fn env_or<T: std::str::FromStr + Copy>(name: &str, default: T) -> T {
+    parse_env(name, default).unwrap_or(default)
+}
+
+// called by realm::env::init()
+pub fn init() -> crate::base::Result<()> {
+    parse_env("REALM_TASK_MAX_ATTEMPTS", 0i32)?;
+    parse_env("REALM_TASK_BACKOFF_SECS", 0i64)?;
+    parse_env("REALM_TASK_BACKOFF_MAX_SECS", 0i64)?;
+    parse_env("REALM_TASK_LEASE_SECS", 0i64)?;
+    parse_env("REALM_WORKER_THREADS", 0usize)?;
+    parse_env("REALM_TASK_DEDUP_SECS", 0i64)?;
+    parse_env("REALM_WORKER_POLL_SECS", 0u64)?;
+    Ok(())
+}
+
+#[derive(Queryable, QueryableByName, serde::Deserialize)]
 pub struct DBTask {
+    #[sql_type = "diesel::sql_types::Integer"]
     pub id: i32,
+    #[sql_type = "diesel::sql_types::Text"]
     pub path: String,
+    #[sql_type = "diesel::sql_types::Text"]
     pub method: String,
+    #[sql_type = "diesel::sql_types::Jsonb"]
     pub data: serde_json::Value,
+    #[sql_type = "diesel::sql_types::Jsonb"]
     pub cookies: serde_json::Value,
+    #[sql_type = "diesel::sql_types::Integer"]
     pub number_tries: i32,
+    #[sql_type = "diesel::sql_types::Nullable<diesel::sql_types::Text>"]
+    pub key: Option<String>,
+    // set by claim(), processed() and failed() only update the task if it is
+    // still held with this lease
+    #[serde(default)]
+    #[sql_type = "diesel::sql_types::Nullable<diesel::sql_types::Timestamptz>"]
+    pub lease_until: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+impl DBTask {
+    pub fn method(&self) -> crate::base::Result<http::Method> {
+        self.method
+            .to_uppercase()
+            .parse()
+            .map_err(|_| format_err!("invalid task method: {}", self.method))
+    }
 }
 
+#[derive(Clone, Debug)]
 pub struct Task {
     pub path: String,
     pub method: String,
     pub data: serde_json::Value,
     pub cookies: serde_json::Value,
+    // not run before this time, None means now
+    pub run_at: Option<chrono::DateTime<chrono::Utc>>,
+    // idempotency key, see REALM_TASK_DEDUP_SECS
+    pub key: Option<String>,
 }
 
+impl Task {
+    pub fn new(method: &str, path: &str, data: serde_json::Value) -> Self {
+        Task {
+            path: path.to_string(),
+            method: method.to_string(),
+            data,
+            cookies: json!({}),
+            run_at: None,
+            key: None,
+        }
+    }
+
+    pub fn run_at(mut self, at: chrono::DateTime<chrono::Utc>) -> Self {
+        self.run_at = Some(at);
+        self
+    }
+
+    pub fn key(mut self, key: &str) -> Self {
+        self.key = Some(key.to_string());
+        self
+    }
+}
+
+// key of the task being handled, None if it has none or this is not a task
+pub fn task_key(ctx: &crate::Context) -> Option<String> {
+    ctx.get_header_string(TASK_KEY_HEADER)
+}
+
+// what status() returns, and /realm/task/?id=<id> as json
+#[derive(Queryable, serde::Serialize, Debug)]
+pub struct Status {
+    pub id: i32,
+    pub status: String,
+    pub number_tries: i32,
+    // json returned by the handler of last successful attempt
+    pub result: Option<serde_json::Value>,
+    pub last_error: Option<String>,
+    #[serde(serialize_with = "crate::datetime_serializer")]
+    pub created_on: chrono::DateTime<chrono::Utc>,
+    // of the last attempt
+    #[serde(serialize_with = "crate::option_datetime_serializer")]
+    pub started_on: Option<chrono::DateTime<chrono::Utc>>,
+    #[serde(serialize_with = "crate::option_datetime_serializer")]
+    pub finished_on: Option<chrono::DateTime<chrono::Utc>>,
+    // milliseconds
+    pub duration: Option<i32>,
+    #[serde(skip)]
+    pub created_by: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum TaskStatus {
     Created,
+    // claimed by a worker till realm_task.lease_until
+    InProgress,
+    // last attempt failed, will be retried after run_after
     Failed,
     Processed,
+    // failed REALM_TASK_MAX_ATTEMPTS times, will not be retried
+    Dead,
 }
 
 impl From<TaskStatus> for String {
     fn from(status: TaskStatus) -> Self {
         match status {
             TaskStatus::Created => "created".to_string(),
+            TaskStatus::InProgress => "in_progress".to_string(),
             TaskStatus::Failed => "failed".to_string(),
             TaskStatus::Processed => "processed".to_string(),
+            TaskStatus::Dead => "dead".to_string(),
         }
     }
 }
 
-pub fn latest(
+// jitter is 0.0 to 1.0
+pub fn backoff_with(attempt: i32, base: i64, max: i64, jitter: f64) -> chrono::Duration {
+    let exp = base.saturating_mul(1i64 << (attempt.max(1) - 1).min(32));
+    let capped = exp.min(max).max(0);
+    let half = capped / 2;
+    chrono::Duration::seconds(capped - half + (half as f64 * jitter) as i64)
+}
+
+// delay before retrying a task that failed for attempt-th time
+pub fn backoff(attempt: i32) -> chrono::Duration {
+    backoff_with(
+        attempt,
+        *REALM_TASK_BACKOFF_SECS,
+        *REALM_TASK_BACKOFF_MAX_SECS,
+        rand::random::<f64>(),
+    )
+}
+
+// a task whose worker died (abort, oom etc) during its last allowed attempt
+// would be claimed again forever, it is marked dead instead
+fn expire_leases(conn: &crate::base::pg::RealmConnection) -> crate::base::Result<()> {
+    diesel::sql_query(
+        "UPDATE realm_task SET \
+         status = 'dead', \
+         last_error = 'lease expired', \
+         finished_on = now(), \
+         updated_on = now() \
+         WHERE status = 'in_progress' AND lease_until < now() AND number_tries >= $1",
+    )
+    .bind::<Integer, _>(*REALM_TASK_MAX_ATTEMPTS)
+    .execute(conn)?;
+    Ok(())
+}
+
+// marks upto limit runnable tasks in_progress for this worker, highest priority
+// and then oldest first. Rows locked by other workers are skipped, so no two
+// workers get the same task. The attempt is counted here, so a task whose
+// worker died before finishing still ends up dead, see expire_leases().
+pub fn claim(
     conn: &crate::base::pg::RealmConnection,
     limit: i64,
 ) -> crate::base::Result<Vec<DBTask>> {
-    use crate::schema::realm_task;
-    realm_task::table
-        .select((
-            realm_task::id,
-            realm_task::path,
-            realm_task::method,
-            realm_task::data,
-            realm_task::cookies,
-            realm_task::number_tries,
-        ))
-        .filter(realm_task::status.eq("created"))
-        .limit(limit)
-        .load(conn)
-        .map_err(|e| e.into())
+    expire_leases(conn)?;
+    diesel::sql_query(
+        "UPDATE realm_task SET \
+         status = 'in_progress', \
+         number_tries = number_tries + 1, \
+         lease_until = now() + make_interval(secs => $2), \
+         started_on = now(), \
+         finished_on = NULL, \
+         duration = NULL, \
+         updated_on = now() \
+         WHERE id IN ( \
+         SELECT id FROM realm_task \
+         WHERE \
+         (status IN ('created', 'failed') AND run_after <= now()) \
+         OR (status = 'in_progress' AND lease_until < now() AND number_tries < $3) \
+         ORDER BY priority DESC, created_on ASC \
+         LIMIT $1 \
+         FOR UPDATE SKIP LOCKED \
+         ) \
+         RETURNING id, path, method, data, cookies, number_tries, key, lease_until",
+    )
+    .bind::<diesel::sql_types::BigInt, _>(limit)
+    .bind::<diesel::sql_types::Double, _>(*REALM_TASK_LEASE_SECS as f64)
+    .bind::<Integer, _>(*REALM_TASK_MAX_ATTEMPTS)
+    .load(conn)
+    .map_err(Into::into)
+}
+
+// milliseconds since the current attempt was claimed
+fn elapsed() -> diesel::expression::SqlLiteral<Nullable<Integer>> {
+    diesel::dsl::sql("(extract(epoch from now() - started_on) * 1000)::integer")
+}
+
+// json to store as task result for what the handler returned, only json
+// responses have one
+pub fn result_json(response: &crate::Response) -> Option<serde_json::Value> {
+    match response {
+        crate::Response::JSON { data: Ok(v), .. } => Some(v.clone()),
+        _ => None,
+    }
 }
 
-pub fn updated_status(
+// a task whose lease expired may have been claimed by another worker, which
+// now owns it, so finishing it here is an error
+fn lease_lost(task: &DBTask, updated: usize) -> crate::base::Result<()> {
+    if updated == 0 {
+        return Err(format_err!(
+            "lease of task {} expired before it finished",
+            task.id
+        ));
+    }
+    Ok(())
+}
+
+// task is as returned by claim()
+pub fn processed(
     conn: &crate::base::pg::RealmConnection,
-    id: i32,
-    number_tries: i32,
-    status: TaskStatus,
+    task: &DBTask,
+    result: Option<serde_json::Value>,
 ) -> crate::base::Result<()> {
     use crate::schema::realm_task;
-    let status: String = status.into();
-    diesel::update(realm_task::table)
+
+    let status: String = TaskStatus::Processed.into();
+    let updated = diesel::update(realm_task::table)
         .set((
             realm_task::status.eq(status),
-            realm_task::number_tries.eq(number_tries + 1),
+            realm_task::result.eq(result),
+            realm_task::finished_on.eq(diesel::dsl::now),
+            realm_task::duration.eq(elapsed()),
             realm_task::updated_on.eq(chrono::Utc::now()),
         ))
+        .filter(realm_task::id.eq(task.id))
+        .filter(realm_task::status.eq("in_progress"))
+        .filter(realm_task::lease_until.eq(task.lease_until))
+        .execute(conn)?;
+    lease_lost(task, updated)
+}
+
+// task, as returned by claim(), failed its number_tries-th attempt, schedules a
+// retry or marks it dead
+pub fn failed(
+    conn: &crate::base::pg::RealmConnection,
+    task: &DBTask,
+    error: &str,
+) -> crate::base::Result<TaskStatus> {
+    use crate::schema::realm_task;
+
+    let tries = task.number_tries;
+    let now = chrono::Utc::now();
+    let status = if tries >= *REALM_TASK_MAX_ATTEMPTS {
+        TaskStatus::Dead
+    } else {
+        TaskStatus::Failed
+    };
+    let s: String = status.into();
+
+    let updated = diesel::update(realm_task::table)
+        .set((
+            realm_task::status.eq(s),
+            realm_task::last_error.eq(error),
+            realm_task::run_after.eq(now + backoff(tries)),
+            realm_task::finished_on.eq(diesel::dsl::now),
+            realm_task::duration.eq(elapsed()),
+            realm_task::updated_on.eq(now),
+        ))
+        .filter(realm_task::id.eq(task.id))
+        .filter(realm_task::status.eq("in_progress"))
+        .filter(realm_task::lease_until.eq(task.lease_until))
+        .execute(conn)?;
+    lease_lost(task, updated)?;
+    Ok(status)
+}
+
+pub fn status(
+    conn: &crate::base::pg::RealmConnection,
+    id: i32,
+) -> crate::base::Result<Option<Status>> {
+    use crate::schema::realm_task;
+
+    realm_task::table
+        .select((
+            realm_task::id,
+            realm_task::status,
+            realm_task::number_tries,
+            realm_task::result,
+            realm_task::last_error,
+            realm_task::created_on,
+            realm_task::started_on,
+            realm_task::finished_on,
+            realm_task::duration,
+            realm_task::created_by,
+        ))
         .filter(realm_task::id.eq(id))
-        .execute(conn)
-        .map(|_| ())
+        .first(conn)
+        .optional()
         .map_err(Into::into)
 }
 
-/*
-   data,
-   status
-*/
-
-pub fn create_realm_tasks<UD>(
-    in_: &crate::base::In<UD>,
-    tasks: Vec<Task>,
-) -> crate::base::Result<()>
+// a task is visible to the user who created it, and to everyone in dev mode
+pub fn status_json<UD>(in_: &crate::base::In<UD>, id: i32) -> crate::Result
 where
     UD: crate::UserData,
 {
+    let s = status(in_.conn, id)?
+        .filter(|s| in_.is_dev() || (s.created_by.is_some() && s.created_by == in_.user_id()));
+    match s {
+        Some(s) => crate::json(&s),
+        None => Err(crate::Error::PageNotFound {
+            message: format!("task not found: {}", id),
+        }
+        .into()),
+    }
+}
+
+// returns id of the created task, or of the existing one with same key
+pub fn insert(
+    conn: &crate::base::pg::RealmConnection,
+    task: Task,
+    created_by: Option<String>,
+    now: chrono::DateTime<chrono::Utc>,
+) -> crate::base::Result<i32> {
+    insert_(conn, task, created_by, now).map(|(id, _)| id)
+}
+
+// also returns if a task that can be run right away was created
+fn insert_(
+    conn: &crate::base::pg::RealmConnection,
+    task: Task,
+    created_by: Option<String>,
+    now: chrono::DateTime<chrono::Utc>,
+) -> crate::base::Result<(i32, bool)> {
     use crate::schema::realm_task;
-    let mut v = vec![];
-    for task in tasks.into_iter() {
-        v.push((
+
+    if let Some(ref key) = task.key {
+        // key of a finished task older than dedup window is released, so it can
+        // be reused, a task still pending or running keeps it
+        let done: Vec<String> = vec![TaskStatus::Processed.into(), TaskStatus::Dead.into()];
+        diesel::update(realm_task::table)
+            .set(realm_task::key.eq(None::<String>))
+            .filter(realm_task::key.eq(key))
+            .filter(realm_task::status.eq_any(done))
+            .filter(
+                realm_task::created_on.lt(now - chrono::Duration::seconds(*REALM_TASK_DEDUP_SECS)),
+            )
+            .execute(conn)?;
+    }
+
+    let run_after = task.run_at.unwrap_or(now);
+    let ids: Vec<i32> = diesel::insert_into(realm_task::table)
+        .values((
             realm_task::method.eq(task.method),
             realm_task::path.eq(task.path),
             realm_task::data.eq(task.data),
@@ -96,74 +432,475 @@ where
             realm_task::status.eq("created"),
             realm_task::number_tries.eq(0),
             realm_task::priority.eq(0),
-            realm_task::created_on.eq(in_.now),
-            realm_task::updated_on.eq(in_.now),
-        ));
+            realm_task::run_after.eq(run_after),
+            realm_task::key.eq(&task.key),
+            realm_task::created_by.eq(created_by),
+            realm_task::created_on.eq(now),
+            realm_task::updated_on.eq(now),
+        ))
+        .on_conflict(realm_task::key)
+        .do_nothing()
+        .returning(realm_task::id)
+        .get_results(conn)?;
+    let due = !ids.is_empty() && run_after <= now;
+    #[cfg(feature = "postgres")]
+    {
+        if due {
+            notify(conn)?;
+        }
+    }
+
+    match (ids.first(), task.key) {
+        (Some(id), _) => Ok((*id, due)),
+        (None, Some(key)) => realm_task::table
+            .select(realm_task::id)
+            .filter(realm_task::key.eq(key))
+            .first(conn)
+            .map(|id| (id, false))
+            .map_err(Into::into),
+        (None, None) => Err(format_err!("task was not inserted")),
     }
-    diesel::insert_into(realm_task::table)
-        .values(&v)
-        .execute(in_.conn)
-        .map_err(Into::into)
-        .map(|_| ())
 }
 
-#[macro_export]
-macro_rules! realm_worker {
-    ($e:expr) => {{
-
-        fn handle_rows(conn: &realm::base::pg::RealmConnection) -> realm::base::Result<usize>
-        {
-            let tasks = realm::bojack::latest(conn, 5)?;
-            let count = tasks.len();
-            if count > 0 {
-                println!("picked event from realm_task: {}", count);
+pub fn create_realm_task<UD>(in_: &crate::base::In<UD>, task: Task) -> crate::base::Result<i32>
+where
+    UD: crate::UserData,
+{
+    let (id, due) = insert_(in_.conn, task, in_.user_id(), in_.now)?;
+    if due {
+        in_.task_created.replace(true);
+    }
+    Ok(id)
+}
+
+// returns ids of the tasks, in same order
+pub fn create_realm_tasks<UD>(
+    in_: &crate::base::In<UD>,
+    tasks: Vec<Task>,
+) -> crate::base::Result<Vec<i32>>
+where
+    UD: crate::UserData,
+{
+    tasks
+        .into_iter()
+        .map(|task| create_realm_task(in_, task))
+        .collect()
+}
+
+// Recurring tasks are declared in code at startup:
+//
+//     realm::worker::schedule(
+//         "nightly-cleanup",
+//         "30 3 * * *",
+//         realm::worker::Task::new("POST", "/api/cleanup/", json!({})),
+//     )?;
+//
+// Worker loop calls enqueue_due(), which inserts a task for every occurrence
+// that is due since the process started, including ones that fell due while
+// the loop was busy running a long task, upto MAX_CATCH_UP per schedule at a
+// time, older ones beyond that are skipped. realm_task has a unique constraint
+// on (schedule, scheduled_for), so an occurrence is enqueued exactly once even
+// with many workers. Occurrences missed while no worker was running are not
+// caught up.
+struct Scheduled {
+    name: String,
+    schedule: crate::cron::Schedule,
+    task: Task,
+    next: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+const MAX_CATCH_UP: usize = 100;
+
+lazy_static! {
+    static ref SCHEDULES: antidote::Mutex<Vec<Scheduled>> = antidote::Mutex::new(vec![]);
+}
+
+pub fn schedule(name: &str, cron: &str, task: Task) -> crate::base::Result<()> {
+    let schedule: crate::cron::Schedule = cron
+        .parse()
+        .map_err(|e| format_err!("schedule {}: {}", name, e))?;
+    let next = schedule.next_after(chrono::Utc::now());
+
+    let mut schedules = SCHEDULES.lock();
+    schedules.retain(|s| s.name != name);
+    schedules.push(Scheduled {
+        name: name.to_string(),
+        schedule,
+        task,
+        next,
+    });
+    Ok(())
+}
+
+// returns number of tasks inserted, zero if another worker already did
+pub fn enqueue_due(conn: &crate::base::pg::RealmConnection) -> crate::base::Result<usize> {
+    use crate::schema::realm_task;
+
+    let now = chrono::Utc::now();
+    // due occurrences are found under the lock and inserted after releasing it,
+    // so other worker threads do not wait on the inserts
+    let due: Vec<(String, Task, Vec<chrono::DateTime<chrono::Utc>>)> = SCHEDULES
+        .lock()
+        .iter()
+        .filter_map(|s| {
+            let mut due = vec![];
+            let mut next = s.next;
+            while let Some(at) = next.filter(|at| *at <= now) {
+                due.push(at);
+                next = s.schedule.next_after(at);
             }
-            for task in tasks.into_iter() {
-                let method = {
-                    // TODO: use task.method
-                    http::Method::POST
-                };
-                match $e(&realm::Context::from(method, task.path.as_str(), task.data, serde_json::from_value(task.cookies)?)) {
-                    Ok(t) => {
-                        realm::bojack::updated_status(
-                            conn,
-                            task.id,
-                            task.number_tries,
-                            realm::bojack::TaskStatus::Processed,
-                        )?;
-                        println!("task_processed: {}", task.id);
-                        // observer::observe_string(
-                        //     "task_processed",
-                        //     format!("{}", task.id).as_str(),
-                        // );
-                    }
-                    Err(e) => {
-                        realm::bojack::updated_status(
-                            conn,
-                            task.id,
-                            task.number_tries,
-                            realm::bojack::TaskStatus::Failed,
-                        )?;
-                        println!("task_process_error: {}", e);
-                        // observer::observe_string("process_err", format!("{}", e).as_str());
-                    }
-                };
+            if due.len() > MAX_CATCH_UP {
+                observer::observe_string(
+                    "schedule_skipped",
+                    format!("{}: {}", s.name, due.len() - MAX_CATCH_UP).as_str(),
+                );
+                due.drain(..due.len() - MAX_CATCH_UP);
+            }
+            if due.is_empty() {
+                None
+            } else {
+                Some((s.name.clone(), s.task.clone(), due))
             }
-            Ok(count)
+        })
+        .collect();
+
+    let mut count = 0;
+    for (name, task, due) in due.into_iter() {
+        for at in due.into_iter() {
+            count += diesel::insert_into(realm_task::table)
+                .values((
+                    realm_task::method.eq(&task.method),
+                    realm_task::path.eq(&task.path),
+                    realm_task::data.eq(&task.data),
+                    realm_task::cookies.eq(&task.cookies),
+                    realm_task::status.eq("created"),
+                    realm_task::number_tries.eq(0),
+                    realm_task::priority.eq(0),
+                    realm_task::run_after.eq(at),
+                    realm_task::schedule.eq(&name),
+                    realm_task::scheduled_for.eq(at),
+                    realm_task::created_on.eq(now),
+                    realm_task::updated_on.eq(now),
+                ))
+                .on_conflict_do_nothing()
+                .execute(conn)?;
+            enqueued(name.as_str(), at);
+        }
+    }
+    Ok(count)
+}
+
+// moves the schedule past an occurrence only once it is inserted, so one that
+// failed to insert is tried again in next call
+fn enqueued(name: &str, at: chrono::DateTime<chrono::Utc>) {
+    if let Some(s) = SCHEDULES.lock().iter_mut().find(|s| s.name == name) {
+        if s.next.map(|next| next <= at).unwrap_or(false) {
+            s.next = s.schedule.next_after(at);
+        }
+    }
+}
+
+// Tasks are run by a handler, which gets a Context built from the task, same
+// as a request:
+//
+//     realm::worker::run(|ctx| match ctx.pm() {
+//         ("/api/cleanup/", &http::Method::POST) => cleanup(ctx),
+//         _ => realm::json_ok(),
+//     });
+//
+// RealmService::worker() runs the app's Middleware as the handler. A task is
+// processed if the handler returns Ok, and retried as described above if it
+// returns Err.
+pub type Handler = dyn Fn(&crate::Context) -> crate::Result + Send + Sync;
+
+fn context(task: &DBTask) -> crate::base::Result<crate::Context> {
+    let mut ctx = crate::Context::from(
+        task.method()?,
+        task.path.as_str(),
+        task.data.clone(),
+        serde_json::from_value(task.cookies.clone())?,
+    );
+    if let Some(v) = task
+        .key
+        .as_ref()
+        .and_then(|k| http::HeaderValue::from_str(k.as_str()).ok())
+    {
+        ctx.headers.insert(TASK_KEY_HEADER, v);
+    }
+    Ok(ctx)
+}
+
+// claims and runs one task, returns number of tasks run
+pub fn run_once(
+    conn: &crate::base::pg::RealmConnection,
+    handler: &Handler,
+) -> crate::base::Result<usize> {
+    let tasks = claim(conn, 1)?;
+    let count = tasks.len();
+    if count > 0 {
+        println!("picked event from realm_task: {}", count);
+    }
+    for task in tasks.into_iter() {
+        // a panicking handler fails the task instead of killing the worker
+        let r = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            context(&task).and_then(|ctx| handler(&ctx))
+        }))
+        .unwrap_or_else(|p| Err(format_err!("handler panicked: {}", panic_message(&*p))));
+        match r {
+            Ok(r) => {
+                processed(conn, &task, result_json(&r))?;
+                println!("task_processed: {}", task.id);
+            }
+            Err(e) => {
+                let status = failed(conn, &task, e.to_string().as_str())?;
+                println!("task_process_error: {}, {:?}: {}", task.id, status, e);
+            }
+        };
+    }
+    Ok(count)
+}
+
+fn panic_message(payload: &(dyn std::any::Any + Send)) -> &str {
+    if let Some(s) = payload.downcast_ref::<&str>() {
+        s
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s.as_str()
+    } else {
+        "unknown"
+    }
+}
+
+// wakes up a waiting worker
+pub fn wake() {
+    // channel holds at most one wake up, more are not needed
+    let _ = WAKE.0.try_send(());
+}
+
+// returns true if woken up before timeout
+pub fn wait(timeout: std::time::Duration) -> bool {
+    WAKE.1.recv_timeout(timeout).is_ok()
+}
+
+// NOTIFY is delivered when the transaction commits, not before
+#[cfg(feature = "postgres")]
+pub fn notify(conn: &crate::base::pg::RealmConnection) -> crate::base::Result<()> {
+    diesel::sql_query(format!("NOTIFY {}", CHANNEL)).execute(conn)?;
+    Ok(())
+}
+
+// the postgres client is built without tls support, returns DATABASE_URL
+// without sslmode, which the client does not understand, or None if sslmode
+// requires tls
+#[cfg(feature = "postgres")]
+fn listen_url(url: &str) -> Option<String> {
+    let mut u = match url::Url::parse(url) {
+        Ok(u) => u,
+        Err(_) => return Some(url.to_string()),
+    };
+    let pairs: Vec<(String, String)> = u.query_pairs().into_owned().collect();
+    let sslmode = pairs
+        .iter()
+        .find(|(k, _)| k == "sslmode")
+        .map(|(_, v)| v.as_str());
+    if let Some("require") | Some("verify-ca") | Some("verify-full") = sslmode {
+        return None;
+    }
+
+    let rest: Vec<&(String, String)> = pairs.iter().filter(|(k, _)| k != "sslmode").collect();
+    if rest.is_empty() {
+        u.set_query(None);
+    } else {
+        u.query_pairs_mut()
+            .clear()
+            .extend_pairs(rest.iter().map(|(k, v)| (k.as_str(), v.as_str())));
+    }
+    Some(u.to_string())
+}
+
+#[cfg(feature = "postgres")]
+fn listen_(url: &str) -> crate::base::Result<()> {
+    use fallible_iterator::FallibleIterator;
+
+    let conn = postgres_client::Connection::connect(url, postgres_client::TlsMode::None)?;
+    conn.execute(format!("LISTEN {}", CHANNEL).as_str(), &[])?;
+    let notifications = conn.notifications();
+    let mut iter = notifications.timeout_iter(std::time::Duration::from_secs(1));
+    while !crate::env::ctrl_c()? {
+        if iter.next()?.is_some() {
+            wake();
+        }
+    }
+    Ok(())
+}
+
+// forwards notifications on CHANNEL to wake(), reconnecting on errors. If it
+// can not connect workers still poll every REALM_WORKER_POLL_SECS.
+#[cfg(feature = "postgres")]
+fn listen() {
+    let url = match std::env::var("DATABASE_URL") {
+        Ok(url) => url,
+        Err(_) => return,
+    };
+    let url = match listen_url(url.as_str()) {
+        Some(url) => url,
+        None => {
+            eprintln!("realm_task listen needs tls, which is not supported, workers will poll");
+            return;
         }
+    };
 
-        let conn = realm::base::pg::connection();
-        while !realm::env::ctrl_c().expect("ctrl-c issue") {
-            let c = match handle_rows(&conn) {
-                Ok(c) => c,
-                Err(e) => {
-                    observer::observe_string("main_process_err", format!("{}", e).as_str());
-                    0
+    let r = std::thread::Builder::new()
+        .name("realm-worker-listen".to_string())
+        .spawn(move || {
+            // same error again and again is logged once
+            let mut last_error = String::new();
+            while !crate::env::ctrl_c().unwrap_or(true) {
+                if let Err(e) = listen_(url.as_str()) {
+                    let e = e.to_string();
+                    if e != last_error {
+                        eprintln!("realm_task listen failed, will retry: {}", e);
+                        last_error = e;
+                    }
+                    std::thread::sleep(std::time::Duration::from_secs(*REALM_WORKER_POLL_SECS));
                 }
-            };
-            if c == 0 {
-                std::thread::sleep(std::time::Duration::from_secs(2));
+            }
+        });
+    if let Err(e) = r {
+        eprintln!("failed to start realm_task listener: {}", e);
+    }
+}
+
+// runs tasks one at a time, along with scheduled tasks, till ctrl-c
+pub fn run_loop(handler: &Handler) {
+    let conn = crate::base::pg::connection();
+    while !crate::env::ctrl_c().expect("ctrl-c issue") {
+        if let Err(e) = enqueue_due(&conn) {
+            observer::observe_string("schedule_err", format!("{}", e).as_str());
+        }
+        let c = match run_once(&conn, handler) {
+            Ok(c) => c,
+            Err(e) => {
+                observer::observe_string("main_process_err", format!("{}", e).as_str());
+                0
             }
         };
-    }};
+        if c == 0 {
+            wait(std::time::Duration::from_secs(*REALM_WORKER_POLL_SECS));
+        }
+    }
+}
+
+// runs REALM_WORKER_THREADS threads of run_loop(), till ctrl-c
+pub fn run<H>(handler: H)
+where
+    H: Fn(&crate::Context) -> crate::Result + Send + Sync + 'static,
+{
+    if let Err(e) = crate::env::init() {
+        eprintln!("invalid configuration: {}", e);
+        std::process::exit(1);
+    }
+    let handler: std::sync::Arc<Handler> = std::sync::Arc::new(handler);
+    let threads = *REALM_WORKER_THREADS;
+    println!("Starting realm worker, threads: {}", threads);
+    #[cfg(feature = "postgres")]
+    listen();
+    crate::activity_retention::start();
+
+    let handles: Vec<_> = (0..threads.max(1))
+        .map(|i| {
+            let handler = handler.clone();
+            std::thread::Builder::new()
+                .name(format!("realm-worker-{}", i))
+                .spawn(move || run_loop(handler.as_ref()))
+                .expect("failed to start worker thread")
+        })
+        .collect();
+    for h in handles.into_iter() {
+        if h.join().is_err() {
+            eprintln!("worker thread panicked");
+        }
+    }
+    crate::activity_sink::flush();
+}
+
+// same as realm::worker::run($e), kept for apps that used it
+#[macro_export]
+macro_rules! realm_worker {
+    ($e:expr) => {
+        $crate::worker::run($e)
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn backoff() {
+        let secs = |attempt, jitter| super::backoff_with(attempt, 10, 3600, jitter).num_seconds();
+
+        assert_eq!(secs(1, 0.0), 5);
+        assert_eq!(secs(1, 1.0), 10);
+        assert_eq!(secs(3, 1.0), 40);
+        assert_eq!(secs(3, 0.5), 30);
+        assert_eq!(secs(20, 1.0), 3600);
+        assert_eq!(secs(20, 0.0), 1800);
+        assert_eq!(secs(200, 1.0), 3600);
+    }
+
+    #[test]
+    fn context() {
+        let task = super::DBTask {
+            id: 1,
+            path: "/api/send/?to=a".to_string(),
+            method: "post".to_string(),
+            data: json!({"n": 1}),
+            cookies: json!({"sid": "s"}),
+            number_tries: 0,
+            key: Some("send-a".to_string()),
+            lease_until: None,
+        };
+        let ctx = super::context(&task).unwrap();
+        assert_eq!(ctx.pm(), ("/api/send/", &http::Method::POST));
+        assert_eq!(ctx.get_cookie("sid"), Some("s"));
+        assert_eq!(super::task_key(&ctx), Some("send-a".to_string()));
+    }
+
+    #[test]
+    fn panic_message() {
+        let p = std::panic::catch_unwind(|| panic!("boom {}", 1)).unwrap_err();
+        assert_eq!(super::panic_message(&*p), "boom 1");
+        let p = std::panic::catch_unwind(|| panic!("boom")).unwrap_err();
+        assert_eq!(super::panic_message(&*p), "boom");
+    }
+
+    #[cfg(feature = "postgres")]
+    #[test]
+    fn listen_url() {
+        assert_eq!(
+            super::listen_url("postgres://u@h/db?sslmode=disable"),
+            Some("postgres://u@h/db".to_string())
+        );
+        assert_eq!(
+            super::listen_url("postgres://u@h/db?sslmode=prefer&application_name=w"),
+            Some("postgres://u@h/db?application_name=w".to_string())
+        );
+        assert_eq!(super::listen_url("postgres://u@h/db?sslmode=require"), None);
+    }
+
+    #[test]
+    fn wake() {
+        let timeout = std::time::Duration::from_millis(10);
+        while super::wait(timeout) {}
+
+        super::wake();
+        super::wake();
+        assert!(super::wait(timeout));
+        assert!(!super::wait(timeout));
+    }
+
+    #[test]
+    fn result_json() {
+        let r = crate::json(&json!({"sent": 2})).unwrap();
+        assert_eq!(super::result_json(&r), Some(json!({"sent": 2})));
+        assert_eq!(super::result_json(&crate::Response::NotFound), None);
+    }
 }